@@ -17,7 +17,7 @@ pub struct Veggie {
 
 fn check_veggies() {
     let db = Db::new_temp().unwrap();
-    let mut md =  Veggie::init(db).unwrap();
+    let md =  Veggie::init(db).unwrap();
 
     let g = Veggie {
         name: "garlic".into(),
@@ -33,19 +33,19 @@ fn check_veggies() {
         weight: 2.5,
     };
 
-    r.save(&mut md).unwrap();
-    Veggie::insert(&mut md, &"garlic".into(), &g).unwrap();
+    r.save(&md).unwrap();
+    Veggie::insert(&md, &"garlic".into(), &g).unwrap();
 
-    let _g1 = Veggie::get(&md, &"garlic".into()).unwrap().unwrap();
-    let r1 = Veggie::get(&md, &"rhubarb".into()).unwrap().unwrap();
+    let _g1 = Veggie::get(&md, "garlic").unwrap().unwrap();
+    let r1 = Veggie::get(&md, "rhubarb").unwrap().unwrap();
 
-    let _res = Veggie::find_by_flavor(&md, &"bold".into()).unwrap();
+    let _res = Veggie::find_by_flavor(&md, "bold").unwrap();
 
-    let _res = Veggie::id_by_leaves(&md, &"pointy".into()).unwrap();
+    let _res = Veggie::id_by_leaves(&md, "pointy").unwrap();
 
-    r1.erase(&mut md).unwrap();
+    r1.erase(&md).unwrap();
 
-    Veggie::del(&mut md, &"garlic".into(), &g).unwrap();
+    Veggie::del(&md, &"garlic".into(), &g).unwrap();
 
 }
 
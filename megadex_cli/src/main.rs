@@ -0,0 +1,246 @@
+use failure::{
+    format_err,
+    Error,
+};
+use megadex_rkv::{
+    is_framed,
+    plain_value,
+    Backend,
+    Db,
+    StoreKind,
+    MAIN_STORE,
+};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::process;
+use structopt::StructOpt;
+
+/// Inspect and check a megadex database
+#[derive(StructOpt)]
+#[structopt(name = "megadex")]
+struct Opt {
+    /// The directory holding the database
+    #[structopt(parse(from_os_str))]
+    dir: PathBuf,
+    /// Decode ids, index keys and records as JSON, for collections using the `Json` codec.
+    /// Otherwise they are shown, and taken on the command line, as hex
+    #[structopt(long)]
+    json: bool,
+    /// The maximum number of named stores to open
+    #[structopt(long, default_value = "128")]
+    max_dbs: u32,
+    #[structopt(subcommand)]
+    cmd: Command,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// List the stores and the number of entries in each
+    Stores,
+    /// Print every record of the main store, or every entry of an index
+    Dump {
+        /// The index to print
+        #[structopt(long)]
+        index: Option<String>,
+    },
+    /// Print the record with the given id
+    Get {
+        id: String,
+    },
+    /// Print the ids and records filed under a key of an index
+    Find {
+        index: String,
+        key: String,
+    },
+    /// Check that every index entry points at a record, and with --json that every record is indexed
+    Verify,
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    if let Err(e) = run(&opt) {
+        eprintln!("megadex: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(opt: &Opt) -> Result<(), Error> {
+    let db = Db::builder().read_only(true).max_dbs(opt.max_dbs).open(&opt.dir)?;
+    let format = Format {
+        json: opt.json,
+        framed: is_framed(&db)?,
+    };
+    match opt.cmd {
+        Command::Stores => stores(&db),
+        Command::Dump {
+            ref index,
+        } => dump(&db, &format, index.as_ref().map(|i| i.as_str())),
+        Command::Get {
+            ref id,
+        } => get(&db, &format, id),
+        Command::Find {
+            ref index,
+            ref key,
+        } => find(&db, &format, index, key),
+        Command::Verify => {
+            let problems = verify(&db, &format)?;
+            if problems > 0 {
+                return Err(format_err!("{} problems found", problems));
+            }
+            println!("ok");
+            Ok(())
+        },
+    }
+}
+
+/// How ids, keys and records are shown and parsed
+struct Format {
+    json: bool,
+    /// Whether the records have the envelope header
+    framed: bool,
+}
+
+impl Format {
+    fn show(&self, bytes: &[u8]) -> String {
+        if self.json {
+            if let Ok(value) = serde_json::from_slice::<Value>(bytes) {
+                return value.to_string();
+            }
+        }
+        hex::encode(bytes)
+    }
+
+    /// Records may be behind a version, compression or encryption header, which is stripped first
+    fn show_record(&self, blob: &[u8]) -> String {
+        match plain_value(blob, self.framed) {
+            Ok(value) => self.show(&value),
+            Err(e) => format!("<can't be decoded: {}>", e),
+        }
+    }
+
+    /// A JSON argument which doesn't parse is taken as a string, so quoting string ids is optional
+    fn parse(&self, arg: &str) -> Result<Vec<u8>, Error> {
+        if self.json {
+            let value = serde_json::from_str(arg).unwrap_or_else(|_| Value::String(arg.into()));
+            Ok(serde_json::to_vec(&value)?)
+        } else {
+            hex::decode(arg).map_err(|e| format_err!("{} is not hex: {}", arg, e))
+        }
+    }
+}
+
+fn stores(db: &Db) -> Result<(), Error> {
+    for store in db.stores()? {
+        let kind = match store.kind {
+            StoreKind::Single => "single",
+            StoreKind::Multi => "multi",
+        };
+        println!("{}\t{}\t{}", store.name, kind, store.entries);
+    }
+    Ok(())
+}
+
+fn dump(db: &Db, format: &Format, index: Option<&str>) -> Result<(), Error> {
+    match index {
+        Some(index) => {
+            let store = db.open_multi(index, false)?;
+            db.read(|txn| {
+                txn.scan_dup(store, &mut |key, id| {
+                    println!("{}\t{}", format.show(key), format.show(id));
+                    Ok(true)
+                })
+            })?;
+        },
+        None => {
+            let main = db.open_single(MAIN_STORE, false)?;
+            db.read(|txn| {
+                txn.scan(main, None, &mut |id, blob| {
+                    println!("{}\t{}", format.show(id), format.show_record(blob));
+                    Ok(true)
+                })
+            })?;
+        },
+    }
+    Ok(())
+}
+
+fn get(db: &Db, format: &Format, id: &str) -> Result<(), Error> {
+    let main = db.open_single(MAIN_STORE, false)?;
+    let id = format.parse(id)?;
+    match db.read(|txn| txn.get(main, &id))? {
+        Some(record) => println!("{}", format.show_record(&record)),
+        None => return Err(format_err!("no record with id {}", format.show(&id))),
+    }
+    Ok(())
+}
+
+fn find(db: &Db, format: &Format, index: &str, key: &str) -> Result<(), Error> {
+    let main = db.open_single(MAIN_STORE, false)?;
+    let store = db.open_multi(index, false)?;
+    let key = format.parse(key)?;
+    db.read(|txn| {
+        for id in txn.get_dup(store, &key)? {
+            let record = txn.get(main, &id)?.map(|r| format.show_record(&r)).unwrap_or_else(|| "<missing>".into());
+            println!("{}\t{}", format.show(&id), record);
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Print every inconsistency between the main store and the indexes, returning how many were found.
+/// Without JSON the records can't be decoded, so only the index entries are checked.
+fn verify(db: &Db, format: &Format) -> Result<usize, Error> {
+    let main = db.open_single(MAIN_STORE, false)?;
+    let mut indexes = Vec::new();
+    for store in db.stores()? {
+        if store.kind == StoreKind::Multi {
+            indexes.push((db.open_multi(&store.name, false)?, store.name));
+        }
+    }
+
+    let mut problems = 0;
+    db.read(|txn| {
+        for (index, name) in indexes.iter() {
+            txn.scan_dup(*index, &mut |key, id| {
+                if txn.get(main, id)?.is_none() {
+                    println!("{} {} points at missing record {}", name, format.show(key), format.show(id));
+                    problems += 1;
+                }
+                Ok(true)
+            })?;
+        }
+        if !format.json {
+            return Ok(());
+        }
+        txn.scan(main, None, &mut |id, blob| {
+            let value = match plain_value(blob, format.framed) {
+                Ok(value) => value,
+                Err(e) => {
+                    println!("record {} can't be decoded: {}", format.show(id), e);
+                    problems += 1;
+                    return Ok(true);
+                },
+            };
+            let record: Value = match serde_json::from_slice(&value) {
+                Ok(record) => record,
+                Err(e) => {
+                    println!("record {} is not JSON: {}", format.show(id), e);
+                    problems += 1;
+                    return Ok(true);
+                },
+            };
+            for (index, name) in indexes.iter() {
+                if let Some(field) = record.get(name) {
+                    let key = serde_json::to_vec(field).expect("a JSON value always serializes");
+                    if !txn.get_dup(*index, &key)?.iter().any(|i| &i[..] == id) {
+                        println!("record {} is missing from {} {}", format.show(id), name, field);
+                        problems += 1;
+                    }
+                }
+            }
+            Ok(true)
+        })
+    })?;
+    Ok(problems)
+}
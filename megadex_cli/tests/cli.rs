@@ -0,0 +1,100 @@
+use megadex_rkv::{
+    Backend,
+    Compression,
+    Db,
+    Json,
+    MegadexDb,
+};
+use serde_derive::{
+    Deserialize,
+    Serialize,
+};
+use std::path::Path;
+use std::process::Command;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Fruit {
+    name: String,
+    color: String,
+}
+
+fn megadex(dir: &Path, args: &[&str]) -> (bool, String) {
+    let out = Command::new(env!("CARGO_BIN_EXE_megadex")).arg(dir).args(args).output().unwrap();
+    (out.status.success(), String::from_utf8(out.stdout).unwrap())
+}
+
+#[test]
+fn inspect() {
+    let dir = tempfile::Builder::new().prefix("megadex").tempdir().unwrap();
+    let db = Db::new(dir.path()).unwrap();
+    let md: MegadexDb<Fruit, Json> = MegadexDb::new(db.clone(), &["color"][..]).unwrap();
+    for (name, color) in [("apple", "red"), ("cherry", "red"), ("lime", "green")].iter() {
+        let fruit = Fruit {
+            name: name.to_string(),
+            color: color.to_string(),
+        };
+        md.put(&fruit.name, &fruit, &[("color", &fruit.color)]).unwrap();
+    }
+
+    let (ok, out) = megadex(dir.path(), &["stores"]);
+    assert!(ok);
+    assert_eq!("_main_\tsingle\t3\n_megadex_\tsingle\t1\ncolor\tmulti\t3\n", out);
+
+    let (ok, out) = megadex(dir.path(), &["--json", "get", "lime"]);
+    assert!(ok);
+    assert_eq!("{\"color\":\"green\",\"name\":\"lime\"}\n", out);
+
+    let (ok, out) = megadex(dir.path(), &["--json", "dump", "--index", "color"]);
+    assert!(ok);
+    assert_eq!("\"green\"\t\"lime\"\n\"red\"\t\"apple\"\n\"red\"\t\"cherry\"\n", out);
+
+    // without --json ids are hex encoded
+    let (ok, out) = megadex(dir.path(), &["find", "color", &hex("\"red\"")]);
+    assert!(ok);
+    assert_eq!(2, out.lines().count());
+
+    let (ok, out) = megadex(dir.path(), &["--json", "verify"]);
+    assert!(ok);
+    assert_eq!("ok\n", out);
+
+    // an index entry without a record, and a record without its index entry
+    let color = db.open_multi("color", false).unwrap();
+    db.write(|txn| {
+        txn.put_dup(color, b"\"blue\"", b"\"plum\"")?;
+        txn.delete_dup(color, b"\"green\"", b"\"lime\"")?;
+        Ok(())
+    })
+    .unwrap();
+    let (ok, out) = megadex(dir.path(), &["--json", "verify"]);
+    assert!(!ok);
+    assert_eq!(
+        "color \"blue\" points at missing record \"plum\"\nrecord \"lime\" is missing from color \"green\"\n",
+        out
+    );
+}
+
+#[test]
+fn envelope() {
+    let dir = tempfile::Builder::new().prefix("megadex").tempdir().unwrap();
+    let db = Db::new(dir.path()).unwrap();
+    let md: MegadexDb<Fruit, Json> = MegadexDb::new(db, &["color"][..]).unwrap();
+    let md = md.with_versions().with_compression(Compression::Lz4);
+    let fig = Fruit {
+        name: "fig".into(),
+        color: "purple".repeat(20),
+    };
+    md.put(&fig.name, &fig, &[("color", &fig.color)]).unwrap();
+
+    // the version and compression headers are stripped before the record is decoded
+    let (ok, out) = megadex(dir.path(), &["--json", "get", "fig"]);
+    assert!(ok);
+    assert_eq!(format!("{{\"color\":\"{}\",\"name\":\"fig\"}}\n", fig.color), out);
+
+    let (ok, out) = megadex(dir.path(), &["--json", "verify"]);
+    assert!(ok);
+    assert_eq!("ok\n", out);
+}
+
+fn hex(s: &str) -> String {
+    s.bytes().map(|b| format!("{:02x}", b)).collect()
+}
@@ -1,4 +1,4 @@
-#![recursion_limit = "128"]
+#![recursion_limit = "256"]
 /*!
 MEGADEX
 
@@ -11,6 +11,32 @@ All structs must have either an id field, or a member that is tagged with #[id]
 If a field is tagged with #[indexed] then it will be able to be used to retrieve the struct
 using a generated `find_by_<member>` function
 
+`#[id(auto)]` on a `u64` or `megadex_rkv::Ulid` id field has the collection make up ids, see
+`MegadexDb::insert_new`. Such structs get `insert_new(md, &mut obj)` (and `insert_new_async`) in place of
+`insert`, and `del(md, &id)`.
+
+The lookup functions (`get`, `update`, `find_by_<member>`, `id_by_<member>` and their async variants)
+take any key the field type `Borrow`s, e.g. `Foo::get(&md, "foo")` for a `String` id.
+
+The serialization format can be chosen with a struct level `#[megadex(codec = "json")]`
+attribute. Valid codecs are `bincode` (the default), `json`, `cbor` and `msgpack`. All but
+`bincode` require the matching cargo feature of megadex_rkv.
+
+`#[megadex(ttl_field = "expires_at")]` names a `SystemTime` (or `Option<SystemTime>`) field
+holding the deadline of each record. The collection then keeps track of expiry, see
+`MegadexDb::with_expiry`.
+
+A `u64` field tagged with `#[version]` holds the version of the record, see
+`MegadexDb::with_versions`. `save` then takes `&mut self`, fails with `VersionConflict` if the stored
+record has changed since it was read, and bumps the field.
+
+`#[megadex(soft_delete)]` turns `erase` and `del` into tombstones, which can be brought back with
+`undelete`, see `MegadexDb::with_soft_delete`.
+
+With the `async` feature (along with the `async` feature of megadex_rkv) structs also get
+`init_async`, `save_async`, `erase_async`, `get_async`, `update_async` and `find_by_<member>_async`, which work
+on an `AsyncMegadexDb`.
+
 ```rust
 use megadex_derive::Megadex;
 use megadex_rkv::{Db, MegadexDb, MegadexDbError};
@@ -47,7 +73,10 @@ use syn::{
     DataStruct,
     DeriveInput,
     Field,
+    Lit,
     LitStr,
+    Meta,
+    NestedMeta,
     Type,
 };
 
@@ -55,7 +84,19 @@ fn find_attr_name<'s>(field: &'s Field, name: &str) -> Option<&'s Attribute> {
     field.attrs.iter().find(|a| a.interpret_meta().map(|v| v.name()).expect("no name for attribute?") == name)
 }
 
-#[proc_macro_derive(Megadex, attributes(indexed, id))]
+fn codec_type(name: &str) -> TokenStream2 {
+    let ty = match name {
+        "bincode" => "Bincode",
+        "json" => "Json",
+        "cbor" => "Cbor",
+        "msgpack" => "MsgPack",
+        other => panic!("Unknown megadex codec {}. Expected one of bincode, json, cbor or msgpack", other),
+    };
+    let ident = Ident::new(ty, Span::call_site());
+    quote! { ::megadex_rkv::#ident }
+}
+
+#[proc_macro_derive(Megadex, attributes(indexed, id, megadex, version))]
 pub fn megadex(input: TokenStream) -> TokenStream {
     // Parse the string representation
     let ast: DeriveInput = syn::parse(input).expect("Couldn't parse for getters");
@@ -67,21 +108,94 @@ pub fn megadex(input: TokenStream) -> TokenStream {
 
 struct Builder {
     fields: Vec<Field>,
+    columns: Vec<Ident>,
     id: Option<Field>,
     typename: Ident,
     id_type: Option<Type>,
+    codec: Option<TokenStream2>,
+    ttl_field: Option<Ident>,
+    version_field: Option<Ident>,
+    soft_delete: bool,
+    auto_id: bool,
 }
 
 impl Builder {
     pub fn new(ast: &DeriveInput) -> Builder {
         Builder {
             fields: Vec::new(),
+            columns: Vec::new(),
             id: None,
             id_type: None,
             typename: ast.ident.clone(),
+            codec: None,
+            ttl_field: None,
+            version_field: None,
+            soft_delete: false,
+            auto_id: false,
         }
     }
 
+    /// Handle the struct level `#[megadex(...)]` options
+    fn handle_options(&mut self, attrs: &[Attribute]) {
+        for meta in attrs.iter().filter_map(|a| a.interpret_meta()).filter(|m| m.name() == "megadex") {
+            let list = match meta {
+                Meta::List(list) => list,
+                _ => panic!("Expected a list of options, e.g. #[megadex(codec = \"json\")]"),
+            };
+            for nested in list.nested.iter() {
+                match nested {
+                    NestedMeta::Meta(Meta::NameValue(nv)) if nv.ident == "codec" => {
+                        if let Lit::Str(ref s) = nv.lit {
+                            self.codec = Some(codec_type(&s.value()));
+                        } else {
+                            panic!("The megadex codec must be a string, e.g. codec = \"json\"");
+                        }
+                    },
+                    NestedMeta::Meta(Meta::NameValue(nv)) if nv.ident == "ttl_field" => {
+                        if let Lit::Str(ref s) = nv.lit {
+                            self.ttl_field = Some(Ident::new(&s.value(), Span::call_site()));
+                        } else {
+                            panic!("The megadex ttl_field must be a string, e.g. ttl_field = \"expires_at\"");
+                        }
+                    },
+                    NestedMeta::Meta(Meta::Word(ident)) if ident == "soft_delete" => {
+                        self.soft_delete = true;
+                    },
+                    _ => panic!("Unknown megadex option"),
+                }
+            }
+        }
+    }
+
+    /// The codec of this struct
+    fn codec(&self) -> TokenStream2 {
+        match self.codec {
+            Some(ref codec) => codec.clone(),
+            None => quote! { ::megadex_rkv::Bincode },
+        }
+    }
+
+    /// The concrete `MegadexDb` type for this struct
+    fn mdex_type(&self) -> TokenStream2 {
+        let typename = self.typename.clone();
+        let codec = self.codec();
+        quote! { MegadexDb<#typename, #codec, B> }
+    }
+
+    /// The concrete `AsyncMegadexDb` type for this struct
+    fn async_type(&self) -> TokenStream2 {
+        let typename = self.typename.clone();
+        let codec = self.codec();
+        quote! { ::megadex_rkv::AsyncMegadexDb<#typename, #codec, B> }
+    }
+
+    /// The concrete `ReadOnlyMegadexDb` type for this struct
+    fn read_only_type(&self) -> TokenStream2 {
+        let typename = self.typename.clone();
+        let codec = self.codec();
+        quote! { ::megadex_rkv::ReadOnlyMegadexDb<#typename, #codec, B> }
+    }
+
     pub fn run(&mut self, ast: &DeriveInput) -> TokenStream2 {
         let name = &ast.ident;
         let generics = &ast.generics;
@@ -94,9 +208,14 @@ impl Builder {
         }) = ast.data
         {
             // let _stock_methods = create_stock(name);
+            self.handle_options(&ast.attrs);
             for f in fields.iter() {
+                self.columns.extend(f.ident.clone());
                 let id_attr = find_attr_name(f, "id").is_some();
                 let idx_attr = find_attr_name(f, "indexed").is_some();
+                if find_attr_name(f, "version").is_some() {
+                    self.handle_version(f);
+                }
                 if id_attr {
                     self.handle_id(f);
                 } else if idx_attr {
@@ -104,11 +223,36 @@ impl Builder {
                 }
             }
 
+            if let Some(ref ttl) = self.ttl_field {
+                if !self.columns.contains(ttl) {
+                    panic!("The megadex ttl_field {} is not a field of {}", ttl, name);
+                }
+            }
+
             let impl_self = self.gen_methods();
+            let impl_record = self.gen_record();
+            let impl_auto = if self.auto_id {
+                let id_name = self.id.as_ref().and_then(|id| id.ident.clone()).expect("Expected the id field to have a name");
+                quote! {
+                    impl #impl_generics ::megadex_rkv::AutoRecord for #name #ty_generics #where_clause {
+                        fn set_id(&mut self, id: Self::Id) {
+                            self.#id_name = id;
+                        }
+                    }
+                }
+            } else {
+                quote! {}
+            };
             quote! {
                 impl #impl_generics #name #ty_generics #where_clause {
                     #(#impl_self)*
                 }
+
+                impl #impl_generics ::megadex_rkv::Record for #name #ty_generics #where_clause {
+                    #impl_record
+                }
+
+                #impl_auto
             }
         } else {
             // Nope. This is an Enum. We cannot handle these!
@@ -120,6 +264,13 @@ impl Builder {
         self.fields.push(field.clone());
     }
 
+    fn handle_version(&mut self, field: &Field) {
+        if let Some(ref version) = self.version_field {
+            panic!("There can only be 1 version field specified per struct. Version is already an attribute on {}", version);
+        }
+        self.version_field = field.ident.clone();
+    }
+
     fn handle_id(&mut self, field: &Field) {
         if let Some(ref id) = self.id {
             panic!(
@@ -130,6 +281,53 @@ impl Builder {
             self.id = Some(field.clone());
             self.id_type = Some(field.clone().ty)
         }
+        match find_attr_name(field, "id").and_then(|a| a.interpret_meta()) {
+            Some(Meta::Word(_)) => {},
+            Some(Meta::List(ref list)) if list.nested.len() == 1 => match list.nested[0] {
+                NestedMeta::Meta(Meta::Word(ref ident)) if ident == "auto" => self.auto_id = true,
+                _ => panic!("Unknown id option, expected #[id(auto)]"),
+            },
+            _ => panic!("Unknown id option, expected #[id(auto)]"),
+        }
+    }
+
+    /// The body of the `Record` impl
+    fn gen_record(&self) -> TokenStream2 {
+        let id = self.id.as_ref().expect("At least 1 id attribute field must be specified");
+        let id_name = id.clone().ident.expect("Expected the field to have a name");
+        let id_type = id.ty.clone();
+        let idents = self.fields.iter().map(|f| f.clone().ident.unwrap()).collect::<Vec<Ident>>();
+        let names =
+            idents.iter().map(|i| LitStr::new(i.to_string().as_str(), Span::call_site())).collect::<Vec<LitStr>>();
+        let names2 = names.clone();
+        let expires_at = match self.ttl_field {
+            Some(ref ttl) => quote! {
+                fn expires_at(&self) -> Option<::std::time::SystemTime> {
+                    ::megadex_rkv::Expiry::deadline(&self.#ttl)
+                }
+            },
+            None => quote! {},
+        };
+
+        quote! {
+            type Id = #id_type;
+
+            fn fields() -> &'static [&'static str] {
+                &[ #(#names),* ]
+            }
+
+            fn id(&self) -> &#id_type {
+                &self.#id_name
+            }
+
+            fn index_keys<C: ::megadex_rkv::Codec>(&self)
+                -> Result<Vec<(&'static str, Vec<u8>)>, ::megadex_rkv::MegadexDbError>
+            {
+                Ok(vec![ #((#names2, C::encode(&self.#idents)?)),* ])
+            }
+
+            #expires_at
+        }
     }
 
     fn gen_methods(&self) -> Vec<TokenStream2> {
@@ -163,26 +361,49 @@ impl Builder {
             .map(|field| {
                 let field_name = field.clone().ident.expect("Expected the field to have a name");
                 let field_str = LitStr::new(field_name.to_string().as_str(), Span::call_site());
-                let typename = self.typename.clone();
-                let mdex = quote!{ MegadexDb<#typename> };
+                let mdex = self.mdex_type();
 
                 let fn_find_by = Ident::new(&format!("find_by_{}", field_name), Span::call_site());
 
                 let fn_id_by = Ident::new(&format!("id_by_{}", field_name), Span::call_site());
                 let id_type = self.id_type.as_ref().unwrap().clone();
                 let ty = field.ty.clone();
+                let find_async = if cfg!(feature = "async") {
+                    let fn_find_by_async = Ident::new(&format!("find_by_{}_async", field_name), Span::call_site());
+                    let amdex = self.async_type();
+                    quote! {
+                        pub async fn #fn_find_by_async<B, Q>(md: &#amdex, field: &Q) -> Result<Vec<Self>, MegadexDbError>
+                        where
+                            B: ::megadex_rkv::Backend,
+                            #ty: ::std::borrow::Borrow<Q>,
+                            Q: ::megadex_rkv::serde::Serialize + ?Sized,
+                        {
+                            md.get_by_field(::megadex_rkv::Field::<Self, #ty>::new(#field_str), field).await
+                        }
+                    }
+                } else {
+                    quote! {}
+                };
                 quote! {
-                    pub fn #fn_find_by(md: &#mdex, field: &#ty) -> Result<Vec<Self>, MegadexDbError> {
-                        md.get_by_field(#field_str, field)
+                    pub fn #fn_find_by<B, Q>(md: &#mdex, field: &Q) -> Result<Vec<Self>, MegadexDbError>
+                    where
+                        B: ::megadex_rkv::Backend,
+                        #ty: ::std::borrow::Borrow<Q>,
+                        Q: ::megadex_rkv::serde::Serialize + ?Sized,
+                    {
+                        md.get_by_field(::megadex_rkv::Field::<Self, #ty>::new(#field_str), field)
                     }
 
-                    pub fn #fn_id_by(md: &#mdex, key: &#ty) -> Result<Vec<#id_type>, MegadexDbError> {
-                        let e = md.get_env();
-                        let envlock = e.read()?;
-                        let reader = envlock.read()?;
-                        md.get_ids_by_field(&reader, #field_str, key)
+                    pub fn #fn_id_by<B, Q>(md: &#mdex, key: &Q) -> Result<Vec<#id_type>, MegadexDbError>
+                    where
+                        B: ::megadex_rkv::Backend,
+                        #ty: ::std::borrow::Borrow<Q>,
+                        Q: ::megadex_rkv::serde::Serialize + ?Sized,
+                    {
+                        md.ids_by_field(::megadex_rkv::Field::<Self, #ty>::new(#field_str), key)
                     }
 
+                    #find_async
                 }
             })
             .collect::<Vec<TokenStream2>>();
@@ -193,39 +414,213 @@ impl Builder {
 
         let mytype = self.typename.clone();
 
-        let mdex = quote!{ MegadexDb<#mytype> };
+        let mdex = self.mdex_type();
+        let read_only = self.read_only_type();
+        let fieldvec2 = fieldvec.clone();
 
         let ty = id.ty.clone();
 
+        // records with a ttl field carry their own deadline
+        let (with_expiry, save, insert) = if self.ttl_field.is_some() {
+            (
+                quote! { md.with_expiry() },
+                quote! { md.put_until(&self.#id_name, self, &#(#fieldtuples2)*, ::megadex_rkv::Record::expires_at(self)) },
+                quote! { md.put_until(&id, val, &#(#valtuples2)*, ::megadex_rkv::Record::expires_at(val)) },
+            )
+        } else {
+            (
+                quote! { Ok(md) },
+                quote! { md.put(&self.#id_name, self, &#(#fieldtuples2)*) },
+                quote! { md.put(&id, val, &#(#valtuples2)*) },
+            )
+        };
+
+        // an auto id may not have the type of the indexed fields, so these go by the Record impl,
+        // and new records get their id from insert_new rather than insert
+        let (with_auto_id, save, erase, del_insert) = if self.auto_id {
+            (
+                quote! { .with_auto_id()? },
+                quote! { md.put_record(self).map(|_| ()) },
+                quote! {
+                    match md.remove(&self.#id_name)? {
+                        Some(_) => Ok(()),
+                        None => Err(MegadexDbError::ValueError("Object not found for id".into())),
+                    }
+                },
+                quote! {
+                    pub fn del<B: ::megadex_rkv::Backend>(md: &#mdex, id: &#ty) -> Result<(), MegadexDbError> {
+                        match md.remove(id)? {
+                            Some(_) => Ok(()),
+                            None => Err(MegadexDbError::ValueError("Object not found for id".into())),
+                        }
+                    }
+
+                    pub fn insert_new<B: ::megadex_rkv::Backend>(md: &#mdex, obj: &mut Self) -> Result<#ty, MegadexDbError> {
+                        md.insert_new(obj)
+                    }
+                },
+            )
+        } else {
+            (
+                quote! {},
+                save,
+                quote! { md.del(&self.#id_name, &#(#fieldtuples3)*) },
+                quote! {
+                    pub fn del<B: ::megadex_rkv::Backend>(md: &#mdex, id: &#ty, val: &#mytype) -> Result<(), MegadexDbError> {
+                        md.del(&id, &#(#valtuples)*)
+                    }
+
+                    pub fn insert<B: ::megadex_rkv::Backend>(md: &#mdex, id: &#ty, val: &#mytype) -> Result<(), MegadexDbError> {
+                        #insert
+                    }
+                },
+            )
+        };
+
+        // a version field is filled in from the store, and checked and bumped by save
+        let (with_versions, save) = match self.version_field {
+            Some(ref version) => (
+                quote! { .with_version_field(|rec: &mut Self, v| rec.#version = v) },
+                quote! {
+                    pub fn save<B: ::megadex_rkv::Backend>(&mut self, md: &#mdex) -> Result<(), MegadexDbError> {
+                        self.#version = md.put_record_if_version(self, self.#version)?;
+                        Ok(())
+                    }
+                },
+            ),
+            None => (
+                quote! {},
+                quote! {
+                    pub fn save<B: ::megadex_rkv::Backend>(&self, md: &#mdex) -> Result<(), MegadexDbError> {
+                        #save
+                    }
+                },
+            ),
+        };
+        // deletes only leave a tombstone behind, which undelete removes again
+        let (with_soft_delete, undelete) = if self.soft_delete {
+            (
+                quote! { .with_soft_delete()? },
+                quote! {
+                    pub fn undelete<B, Q>(md: &#mdex, id: &Q) -> Result<Option<Self>, MegadexDbError>
+                    where
+                        B: ::megadex_rkv::Backend,
+                        #ty: ::std::borrow::Borrow<Q>,
+                        Q: ::megadex_rkv::serde::Serialize + ?Sized,
+                    {
+                        md.undelete(id)
+                    }
+                },
+            )
+        } else {
+            (quote! {}, quote! {})
+        };
+        let with_soft_delete2 = with_soft_delete.clone();
+        let with_versions2 = with_versions.clone();
+        let with_expiry2 = with_expiry.clone();
+
         let s = quote! {
-            pub  fn init(db: Db) -> Result<#mdex, MegadexDbError> {
-                MegadexDb::new(db, &#(#fieldvec)*)
+            pub fn init<B: ::megadex_rkv::Backend>(db: B) -> Result<#mdex, MegadexDbError> {
+                let md = MegadexDb::new(db, &#(#fieldvec)*)? #with_versions #with_soft_delete #with_auto_id;
+                #with_expiry
             }
 
-            pub fn save(&self, md: &mut #mdex) -> Result<(), MegadexDbError> {
-                md.put(&self.#id_name, self, &#(#fieldtuples2)*)
+            pub fn init_read_only<B: ::megadex_rkv::Backend>(db: B) -> Result<#read_only, MegadexDbError> {
+                let md = ::megadex_rkv::ReadOnlyMegadexDb::new(db, &#(#fieldvec2)*)? #with_versions2 #with_soft_delete2;
+                #with_expiry2
             }
 
-            pub fn erase(&self, md: &mut #mdex) -> Result<(), MegadexDbError> {
-                md.del(&self.#id_name, &#(#fieldtuples3)*)
+            #save
+
+            pub fn erase<B: ::megadex_rkv::Backend>(&self, md: &#mdex) -> Result<(), MegadexDbError> {
+                #erase
             }
 
-            pub fn get(md: &#mdex, id: &#ty) -> Result<Option<Self>, MegadexDbError> {
+            pub fn get<B, Q>(md: &#mdex, id: &Q) -> Result<Option<Self>, MegadexDbError>
+            where
+                B: ::megadex_rkv::Backend,
+                #ty: ::std::borrow::Borrow<Q>,
+                Q: ::megadex_rkv::serde::Serialize + ?Sized,
+            {
                 md.get(id)
             }
 
-            pub fn del(md: &mut #mdex, id: &#ty, val: &#mytype) -> Result<(), MegadexDbError> {
-                md.del(&id, &#(#valtuples)*)
-            }
+            #del_insert
 
-            pub fn insert(md: &mut #mdex, id: &#ty, val: &#mytype) -> Result<(), MegadexDbError> {
-                md.put(&id, val, &#(#valtuples2)*)
+            pub fn update<B, Q, F>(md: &#mdex, id: &Q, f: F) -> Result<Option<Self>, MegadexDbError>
+            where
+                B: ::megadex_rkv::Backend,
+                #ty: ::std::borrow::Borrow<Q>,
+                Q: ::megadex_rkv::serde::Serialize + ?Sized,
+                F: FnMut(&mut Self),
+            {
+                md.update(id, f)
             }
+
+            #undelete
         };
 
         //panic!(s.to_string());
         streams.push(s);
 
+        if cfg!(feature = "async") {
+            let amdex = self.async_type();
+            let ty = id.ty.clone();
+            let save_async = match self.version_field {
+                Some(ref version) => quote! {
+                    let expected = self.#version;
+                    md.put_if_version(self, expected).await.map(|_| ())
+                },
+                None => quote! { md.put(self).await.map(|_| ()) },
+            };
+            let insert_new_async = if self.auto_id {
+                quote! {
+                    pub async fn insert_new_async<B: ::megadex_rkv::Backend>(md: &#amdex, obj: Self) -> Result<#ty, MegadexDbError> {
+                        md.insert_new(obj).await
+                    }
+                }
+            } else {
+                quote! {}
+            };
+            streams.push(quote! {
+                pub fn init_async<B: ::megadex_rkv::Backend>(db: B) -> Result<#amdex, MegadexDbError> {
+                    ::megadex_rkv::AsyncMegadexDb::new(Self::init(db)?)
+                }
+
+                pub async fn save_async<B: ::megadex_rkv::Backend>(self, md: &#amdex) -> Result<(), MegadexDbError> {
+                    #save_async
+                }
+
+                pub async fn erase_async<B: ::megadex_rkv::Backend>(&self, md: &#amdex) -> Result<(), MegadexDbError> {
+                    match md.del(&self.#id_name).await? {
+                        Some(_) => Ok(()),
+                        None => Err(MegadexDbError::ValueError("Object not found for id".into())),
+                    }
+                }
+
+                pub async fn get_async<B, Q>(md: &#amdex, id: &Q) -> Result<Option<Self>, MegadexDbError>
+                where
+                    B: ::megadex_rkv::Backend,
+                    #ty: ::std::borrow::Borrow<Q>,
+                    Q: ::megadex_rkv::serde::Serialize + ?Sized,
+                {
+                    md.get(id).await
+                }
+
+                pub async fn update_async<B, Q, F>(md: &#amdex, id: &Q, f: F) -> Result<Option<Self>, MegadexDbError>
+                where
+                    B: ::megadex_rkv::Backend,
+                    #ty: ::std::borrow::Borrow<Q>,
+                    Q: ::megadex_rkv::serde::Serialize + ?Sized,
+                    F: FnMut(&mut Self) + Send + 'static,
+                {
+                    md.update(id, f).await
+                }
+
+                #insert_new_async
+            });
+        }
+
         streams
     }
 }
@@ -0,0 +1,82 @@
+#![cfg(feature = "async")]
+
+use megadex_rkv::{ Backend, Db, Field, MemDb };
+
+mod common;
+
+use common::{ veggie, Veggie };
+
+#[test]
+fn async_veggies() {
+    let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    rt.block_on(async {
+        let md = Veggie::init_async(Db::new_temp().unwrap()).unwrap();
+        veggie("garlic", "bold").save_async(&md).await.unwrap();
+        veggie("rhubarb", "sour").save_async(&md).await.unwrap();
+
+        // put moves the index entries of the record it replaces
+        let old = md.put(veggie("garlic", "mild")).await.unwrap();
+        assert_eq!(Some(veggie("garlic", "bold")), old);
+        assert!(Veggie::find_by_flavor_async(&md, "bold").await.unwrap().is_empty());
+        assert_eq!(vec![veggie("garlic", "mild")], Veggie::find_by_flavor_async(&md, "mild").await.unwrap());
+
+        let heavy = Veggie::update_async(&md, "garlic", |v| v.weight *= 2.0).await.unwrap();
+        assert_eq!(Some(2.0), heavy.map(|v| v.weight));
+
+        let rhubarb = Veggie::get_async(&md, "rhubarb").await.unwrap().unwrap();
+        rhubarb.erase_async(&md).await.unwrap();
+        assert!(rhubarb.erase_async(&md).await.is_err());
+        assert_eq!(None, md.get(&"rhubarb".to_string()).await.unwrap());
+    });
+}
+
+#[test]
+fn stream_veggies() {
+    let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    rt.block_on(async {
+        let md = Veggie::init_async(Db::new_temp().unwrap()).unwrap();
+        for i in 0..250 {
+            let flavor = if i % 2 == 0 { "even" } else { "odd" };
+            md.put(veggie(&format!("v{:03}", i), flavor)).await.unwrap();
+        }
+
+        let mut all = md.stream();
+        let mut names = Vec::new();
+        while let Some(v) = all.next().await {
+            names.push(v.unwrap().name);
+        }
+        assert_eq!((0..250).map(|i| format!("v{:03}", i)).collect::<Vec<_>>(), names);
+
+        let mut odd = md.stream_by_field(Field::<Veggie, String>::new("flavor"), "odd").unwrap();
+        let mut count = 0;
+        while let Some(v) = odd.next().await {
+            assert_eq!("odd", v.unwrap().flavor);
+            count += 1;
+        }
+        assert_eq!(125, count);
+        assert!(md.stream_by_field(Field::<Veggie, String>::new("color"), "red").is_err());
+    });
+}
+
+#[test]
+fn panicking_write() {
+    fn check<B: Backend>(db: B) {
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        rt.block_on(async {
+            let md = Veggie::init_async(db).unwrap();
+            veggie("garlic", "bold").save_async(&md).await.unwrap();
+
+            // the panic comes back as an error, the update is rolled back and the writer carries on
+            let res = Veggie::update_async(&md, "garlic", |v| {
+                v.flavor = "mild".into();
+                panic!("no garlic");
+            });
+            assert!(res.await.unwrap_err().to_string().contains("no garlic"));
+            assert_eq!(vec![veggie("garlic", "bold")], Veggie::find_by_flavor_async(&md, "bold").await.unwrap());
+            veggie("rhubarb", "sour").save_async(&md).await.unwrap();
+            assert_eq!(Some(veggie("rhubarb", "sour")), Veggie::get_async(&md, "rhubarb").await.unwrap());
+        });
+    }
+    check(Db::new_temp().unwrap());
+    check(MemDb::new());
+}
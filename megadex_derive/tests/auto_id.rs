@@ -0,0 +1,118 @@
+use megadex_derive::Megadex;
+use megadex_rkv::{ AutoId, Db, MegadexDb, MegadexDbError, Ulid };
+use serde_derive::{ Serialize, Deserialize };
+use std::time::{ Duration, SystemTime };
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Megadex)]
+pub struct Note {
+    #[id(auto)]
+    id: u64,
+    #[indexed]
+    author: String,
+    text: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Megadex)]
+pub struct Event {
+    #[id(auto)]
+    id: Ulid,
+    #[indexed]
+    kind: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Megadex)]
+#[megadex(ttl_field = "expires_at", soft_delete)]
+pub struct Ticket {
+    #[id(auto)]
+    id: u64,
+    #[indexed]
+    owner: String,
+    #[indexed]
+    queue: String,
+    expires_at: SystemTime,
+}
+
+fn note(author: &str, text: &str) -> Note {
+    Note {
+        id: 0,
+        author: author.into(),
+        text: text.into(),
+    }
+}
+
+#[test]
+fn sequence() {
+    let db = Db::new_temp().unwrap();
+    let md = Note::init(db.clone()).unwrap();
+    let mut first = note("ann", "hello");
+    assert_eq!(1, Note::insert_new(&md, &mut first).unwrap());
+    assert_eq!(1, first.id);
+    assert_eq!(2, Note::insert_new(&md, &mut note("bob", "hi")).unwrap());
+
+    // the sequence is kept in the database, not in the handle
+    let md = Note::init(db).unwrap();
+    let mut third = note("ann", "again");
+    assert_eq!(3, Note::insert_new(&md, &mut third).unwrap());
+    assert_eq!(vec![first.clone(), third.clone()], Note::find_by_author(&md, "ann").unwrap());
+
+    // ids which were taken by hand are skipped
+    let mut taken = note("cid", "mine");
+    taken.id = 4;
+    taken.save(&md).unwrap();
+    assert_eq!(5, Note::insert_new(&md, &mut note("cid", "next")).unwrap());
+
+    third.author = "bob".into();
+    third.save(&md).unwrap();
+    assert_eq!(vec![first.clone()], Note::find_by_author(&md, "ann").unwrap());
+    first.erase(&md).unwrap();
+    Note::del(&md, &2).unwrap();
+    assert!(Note::del(&md, &2).is_err());
+    assert_eq!(vec![third], Note::find_by_author(&md, "bob").unwrap());
+}
+
+#[test]
+fn ulid() {
+    let md = Event::init(Db::new_temp().unwrap()).unwrap();
+    let mut ids = Vec::new();
+    for _ in 0..100 {
+        let mut event = Event {
+            id: Ulid(0),
+            kind: "click".into(),
+        };
+        ids.push(Event::insert_new(&md, &mut event).unwrap());
+    }
+    let mut sorted = ids.clone();
+    sorted.sort();
+    sorted.dedup();
+    assert_eq!(ids, sorted);
+
+    let text = ids[0].to_string();
+    assert_eq!(26, text.len());
+    assert_eq!(ids[0], text.parse().unwrap());
+    assert!(ids[0].to_string() < ids[99].to_string());
+    assert_eq!(100, Event::find_by_kind(&md, "click").unwrap().len());
+    assert!(Event::get(&md, &ids[50]).unwrap().is_some());
+}
+
+#[test]
+fn every_feature() {
+    // the bookkeeping of expiry, tombstones and ids shares one store, so this fits the default max_dbs
+    let md = Ticket::init(Db::new_temp().unwrap()).unwrap();
+    let mut ticket = Ticket {
+        id: 0,
+        owner: "ann".into(),
+        queue: "support".into(),
+        expires_at: SystemTime::now() + Duration::from_secs(60),
+    };
+    assert_eq!(1, Ticket::insert_new(&md, &mut ticket).unwrap());
+    ticket.erase(&md).unwrap();
+    assert!(Ticket::find_by_queue(&md, "support").unwrap().is_empty());
+    assert_eq!(Some(ticket), Ticket::undelete(&md, &1).unwrap());
+}
+
+#[test]
+fn exhausted() {
+    assert_eq!(1, u64::next(None).unwrap());
+    assert_eq!(u64::MAX, u64::next(Some(&(u64::MAX - 1))).unwrap());
+    assert!(u64::next(Some(&u64::MAX)).is_err());
+}
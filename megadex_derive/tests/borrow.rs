@@ -0,0 +1,40 @@
+use megadex_derive::Megadex;
+use megadex_rkv::{ Db, Field, MegadexDb, MegadexDbError };
+use serde_derive::{ Serialize, Deserialize };
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Megadex)]
+pub struct Blob {
+    #[id]
+    hash: Vec<u8>,
+    #[indexed]
+    tags: Vec<u8>,
+    size: u64,
+}
+
+#[test]
+fn borrowed_keys() {
+    let md = Blob::init(Db::new_temp().unwrap()).unwrap();
+    let blob = Blob {
+        hash: vec![0xde, 0xad],
+        tags: vec![1, 2],
+        size: 10,
+    };
+    blob.save(&md).unwrap();
+
+    // slices look up the same records as the owned values they borrow from
+    assert_eq!(Some(blob.clone()), Blob::get(&md, &[0xde, 0xad][..]).unwrap());
+    assert_eq!(Some(blob.clone()), Blob::get(&md, &blob.hash).unwrap());
+    assert_eq!(None, Blob::get(&md, &[0xbe, 0xef][..]).unwrap());
+    assert_eq!(vec![blob.clone()], Blob::find_by_tags(&md, &[1, 2][..]).unwrap());
+    assert_eq!(vec![blob.hash.clone()], Blob::id_by_tags(&md, &blob.tags).unwrap());
+
+    // MegadexDb checks the keys against the types of the id and the field, so literals are inferred
+    let tags = Field::<Blob, Vec<u8>>::new("tags");
+    assert_eq!(vec![blob.clone()], md.get_by_field(tags, &[1, 2][..]).unwrap());
+    let ids: Vec<Vec<u8>> = md.ids_by_field(tags, &[1, 2][..]).unwrap();
+    assert_eq!(vec![blob.hash.clone()], ids);
+    assert_eq!(Some(blob.clone()), md.get(&[0xde, 0xad][..]).unwrap());
+
+    let updated = Blob::update(&md, &b"\xde\xad"[..], |b| b.size = 20).unwrap().unwrap();
+    assert_eq!(Some(updated), md.get(&b"\xde\xad"[..]).unwrap());
+}
@@ -0,0 +1,39 @@
+use megadex_derive::Megadex;
+use megadex_rkv::{ Db, MegadexDb, MegadexDbError };
+use serde_derive::{ Serialize, Deserialize };
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Megadex)]
+#[megadex(codec = "json")]
+pub struct Fruit {
+    #[id]
+    name: String,
+    #[indexed]
+    color: String,
+    weight: f64,
+}
+
+#[test]
+fn check_json_fruit() {
+    let db = Db::new_temp().unwrap();
+    let md = Fruit::init(db).unwrap();
+
+    let a = Fruit {
+        name: "apple".into(),
+        color: "red".into(),
+        weight: 0.2,
+    };
+
+    a.save(&md).unwrap();
+
+    let a1 = Fruit::get(&md, "apple").unwrap().unwrap();
+    assert_eq!(a, a1);
+
+    let res = Fruit::find_by_color(&md, "red").unwrap();
+    assert_eq!(res, vec![a1]);
+
+    let ids = Fruit::id_by_color(&md, "red").unwrap();
+    assert_eq!(ids, vec!["apple".to_string()]);
+
+    a.erase(&md).unwrap();
+    assert_eq!(Fruit::get(&md, "apple").unwrap(), None);
+}
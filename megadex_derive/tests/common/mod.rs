@@ -0,0 +1,40 @@
+//! The structs shared by the derive tests. Each test crate only uses some of them.
+#![allow(dead_code)]
+
+use megadex_derive::Megadex;
+use megadex_rkv::{ MegadexDb, MegadexDbError };
+use serde_derive::{ Serialize, Deserialize };
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Megadex)]
+pub struct Veggie {
+    #[id]
+    pub name: String,
+    #[indexed]
+    pub flavor: String,
+    pub weight: f64,
+}
+
+pub fn veggie(name: &str, flavor: &str) -> Veggie {
+    Veggie {
+        name: name.into(),
+        flavor: flavor.into(),
+        weight: 1.0,
+    }
+}
+
+/// A `Veggie` which is only marked as deleted when it is erased
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Megadex)]
+#[megadex(soft_delete)]
+pub struct SoftVeggie {
+    #[id]
+    pub name: String,
+    #[indexed]
+    pub flavor: String,
+}
+
+pub fn soft_veggie(name: &str, flavor: &str) -> SoftVeggie {
+    SoftVeggie {
+        name: name.into(),
+        flavor: flavor.into(),
+    }
+}
@@ -0,0 +1,88 @@
+use megadex_derive::Megadex;
+use megadex_rkv::{ Conflict, Db, ImportStats, MegadexDb, MegadexDbError, MemDb };
+use serde_derive::{ Serialize, Deserialize };
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Megadex)]
+pub struct Veggie {
+    #[id]
+    name: String,
+    #[indexed]
+    flavor: String,
+    weight: f64,
+}
+
+#[test]
+fn export_import_csv() {
+    let md = Veggie::init(Db::new_temp().unwrap()).unwrap();
+    let garlic = Veggie {
+        name: "garlic".into(),
+        flavor: "bold".into(),
+        weight: 0.5,
+    };
+    garlic.save(&md).unwrap();
+
+    let mut out = Vec::new();
+    assert_eq!(1, md.export_csv(&mut out).unwrap());
+    assert_eq!("name,flavor,weight\ngarlic,bold,0.5\n", String::from_utf8(out).unwrap());
+
+    let sheet = "\
+flavor,weight,name
+bold,2.5,rhubarb
+mild,heavy,leek
+bold,0.5,garlic
+sweet,1.0,garlic
+";
+    let copy = Veggie::init(MemDb::new()).unwrap();
+    let report = copy.import_csv(sheet.as_bytes(), Conflict::Fail).unwrap();
+    assert_eq!(ImportStats { inserted: 2, replaced: 0, skipped: 0 }, report.stats);
+    let lines = report.errors.iter().map(|e| e.line).collect::<Vec<usize>>();
+    assert_eq!(vec![3, 5], lines);
+    match report.errors[0].error {
+        MegadexDbError::CodecError(_) => (),
+        ref e => panic!("Expected a codec error, got {:?}", e),
+    }
+    assert_eq!(MegadexDbError::ValueError("line 5: the id is already taken".into()), report.errors[1].error);
+
+    let bold = Veggie::find_by_flavor(&copy, "bold").unwrap();
+    assert_eq!(vec!["garlic", "rhubarb"], bold.iter().map(|v| v.name.as_str()).collect::<Vec<&str>>());
+
+    // the empty collection still gets its header
+    let mut out = Vec::new();
+    Veggie::init(MemDb::new()).unwrap().export_csv(&mut out).unwrap();
+    assert_eq!("name,flavor,weight\n", String::from_utf8(out).unwrap());
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Megadex)]
+pub struct Herb {
+    #[id]
+    #[serde(rename = "herb")]
+    name: String,
+    #[indexed]
+    #[serde(rename = "taste")]
+    flavor: String,
+    #[serde(skip)]
+    fresh: bool,
+}
+
+#[test]
+fn serde_names_csv() {
+    let md = Herb::init(MemDb::new()).unwrap();
+    let mut out = Vec::new();
+    md.export_csv(&mut out).unwrap();
+    assert_eq!("herb,taste\n", String::from_utf8(out).unwrap());
+
+    let basil = Herb {
+        name: "basil".into(),
+        flavor: "sweet".into(),
+        fresh: false,
+    };
+    basil.save(&md).unwrap();
+    let mut out = Vec::new();
+    md.export_csv(&mut out).unwrap();
+    assert_eq!("herb,taste\nbasil,sweet\n", String::from_utf8(out.clone()).unwrap());
+
+    let copy = Herb::init(MemDb::new()).unwrap();
+    let report = copy.import_csv(&out[..], Conflict::Fail).unwrap();
+    assert!(report.errors.is_empty());
+    assert_eq!(vec![basil], Herb::find_by_flavor(&copy, "sweet").unwrap());
+}
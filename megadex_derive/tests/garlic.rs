@@ -1,6 +1,6 @@
 
 use megadex_derive::Megadex;
-use megadex_rkv::{ Db, MegadexDb, MegadexDbError };
+use megadex_rkv::{ Db, Field, MegadexDb, MegadexDbError };
 use serde_derive::{ Serialize, Deserialize };
 
 #[derive(Debug, Serialize, Deserialize, Megadex)]
@@ -18,7 +18,7 @@ pub struct Veggie {
 #[test]
 fn check_veggies() {
     let db = Db::new_temp().unwrap();
-    let mut md =  Veggie::init(db).unwrap();
+    let md =  Veggie::init(db.clone()).unwrap();
 
     let g = Veggie {
         name: "garlic".into(),
@@ -34,19 +34,22 @@ fn check_veggies() {
         weight: 2.5,
     };
 
-    r.save(&mut md).unwrap();
-    Veggie::insert(&mut md, &"garlic".into(), &g).unwrap();
+    r.save(&md).unwrap();
+    Veggie::insert(&md, &"garlic".into(), &g).unwrap();
 
-    let _g1 = Veggie::get(&md, &"garlic".into()).unwrap().unwrap();
-    let r1 = Veggie::get(&md, &"rhubarb".into()).unwrap().unwrap();
+    let _g1 = Veggie::get(&md, "garlic").unwrap().unwrap();
+    let r1 = Veggie::get(&md, "rhubarb").unwrap().unwrap();
 
-    let _res = Veggie::find_by_flavor(&md, &"bold".into()).unwrap();
+    let _res = Veggie::find_by_flavor(&md, "bold").unwrap();
 
-    let _res = Veggie::id_by_leaves(&md, &"pointy".into()).unwrap();
+    let _res = Veggie::id_by_leaves(&md, "pointy").unwrap();
 
-    r1.erase(&mut md).unwrap();
+    let ro = Veggie::init_read_only(db.clone()).unwrap();
+    assert_eq!(2, ro.get_by_field(Field::<Veggie, String>::new("flavor"), "bold").unwrap().len());
 
-    Veggie::del(&mut md, &"garlic".into(), &g).unwrap();
+    r1.erase(&md).unwrap();
+
+    Veggie::del(&md, &"garlic".into(), &g).unwrap();
 
 }
 
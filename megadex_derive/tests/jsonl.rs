@@ -0,0 +1,43 @@
+use megadex_rkv::{ Conflict, Db, ImportStats, MegadexDbError, MemDb };
+
+mod common;
+
+use common::{ veggie, Veggie };
+
+#[test]
+fn export_import() {
+    let md = Veggie::init(Db::new_temp().unwrap()).unwrap();
+    for v in [veggie("garlic", "bold"), veggie("rhubarb", "bold"), veggie("leek", "mild")].iter() {
+        v.save(&md).unwrap();
+    }
+    let mut out = Vec::new();
+    assert_eq!(3, md.export_jsonl(&mut out).unwrap());
+    let text = String::from_utf8(out.clone()).unwrap();
+    assert_eq!(3, text.lines().count());
+    assert!(text.contains(r#"{"id":"garlic","value":{"flavor":"bold","name":"garlic","weight":1.0}}"#));
+
+    let copy = Veggie::init(MemDb::new()).unwrap();
+    let stats = copy.import_jsonl(&out[..], Conflict::Fail).unwrap();
+    assert_eq!(ImportStats { inserted: 3, replaced: 0, skipped: 0 }, stats);
+    assert_eq!(2, Veggie::find_by_flavor(&copy, "bold").unwrap().len());
+
+    veggie("garlic", "pungent").save(&md).unwrap();
+    let mut out = Vec::new();
+    md.export_jsonl(&mut out).unwrap();
+
+    let stats = copy.import_jsonl(&out[..], Conflict::Skip).unwrap();
+    assert_eq!(ImportStats { inserted: 0, replaced: 0, skipped: 3 }, stats);
+    let res = copy.import_jsonl(&out[..], Conflict::Fail).err().unwrap();
+    assert_eq!(MegadexDbError::ValueError("line 1: the id is already taken".into()), res);
+
+    let stats = copy.import_jsonl(&out[..], Conflict::Overwrite).unwrap();
+    assert_eq!(ImportStats { inserted: 0, replaced: 3, skipped: 0 }, stats);
+    assert_eq!(vec![veggie("rhubarb", "bold")], Veggie::find_by_flavor(&copy, "bold").unwrap());
+    assert_eq!(vec![veggie("garlic", "pungent")], Veggie::find_by_flavor(&copy, "pungent").unwrap());
+
+    let bad = "\n{\"id\":\"leek\",\"value\":{\"name\":\"leek\"}}\n";
+    match copy.import_jsonl(bad.as_bytes(), Conflict::Overwrite) {
+        Err(MegadexDbError::CodecError(e)) => assert!(e.starts_with("line 2:")),
+        other => panic!("Expected a codec error, got {:?}", other),
+    }
+}
@@ -0,0 +1,88 @@
+
+use megadex_rkv::{ AuditOp, Change, Db, MegadexDbError, Retention };
+use std::thread::sleep;
+use std::time::{ Duration, SystemTime };
+
+mod common;
+
+use common::{ soft_veggie, SoftVeggie };
+
+#[test]
+fn tombstones() {
+    let db = Db::new_temp().unwrap();
+    let md = SoftVeggie::init(db.clone()).unwrap();
+    let garlic = soft_veggie("garlic", "bold");
+    garlic.save(&md).unwrap();
+    soft_veggie("leek", "bold").save(&md).unwrap();
+
+    garlic.erase(&md).unwrap();
+    assert!(garlic.erase(&md).is_err());
+    assert_eq!(None, SoftVeggie::get(&md, "garlic").unwrap());
+    assert_eq!(vec!["leek".to_string()], SoftVeggie::id_by_flavor(&md, "bold").unwrap());
+    assert_eq!(None, SoftVeggie::update(&md, "garlic", |v| v.flavor = "mild".into()).unwrap());
+    let mut out = Vec::new();
+    assert_eq!(1, md.export_jsonl(&mut out).unwrap());
+
+    let all = md.include_deleted();
+    assert_eq!(Some(garlic.clone()), SoftVeggie::get(&all, "garlic").unwrap());
+    assert_eq!(2, SoftVeggie::find_by_flavor(&all, "bold").unwrap().len());
+    let ro = SoftVeggie::init_read_only(db).unwrap();
+    assert_eq!(None, ro.get(&"garlic".to_string()).unwrap());
+    assert!(ro.include_deleted().get(&"garlic".to_string()).unwrap().is_some());
+
+    assert_eq!(Some(garlic.clone()), SoftVeggie::undelete(&md, "garlic").unwrap());
+    assert_eq!(None, SoftVeggie::undelete(&md, "garlic").unwrap());
+    assert_eq!(Some(garlic.clone()), SoftVeggie::get(&md, "garlic").unwrap());
+
+    // the fields passed to del must match the index entries, which stay behind the tombstone
+    let res = SoftVeggie::del(&md, &"garlic".into(), &soft_veggie("garlic", "mild")).unwrap_err();
+    assert_eq!(MegadexDbError::ValueError("Index entry not found for field flavor".into()), res);
+    assert_eq!(Some(garlic.clone()), SoftVeggie::get(&md, "garlic").unwrap());
+
+    // saving over a tombstone brings the record back as well
+    SoftVeggie::del(&md, &"leek".into(), &soft_veggie("leek", "bold")).unwrap();
+    soft_veggie("leek", "mild").save(&md).unwrap();
+    assert_eq!(vec![soft_veggie("leek", "mild")], SoftVeggie::find_by_flavor(&md, "mild").unwrap());
+}
+
+#[test]
+fn purge() {
+    let md = SoftVeggie::init(Db::new_temp().unwrap()).unwrap().with_audit("veggies").unwrap();
+    let garlic = soft_veggie("garlic", "bold");
+    garlic.save(&md).unwrap();
+    garlic.erase(&md).unwrap();
+    let changes = md.subscribe().unwrap();
+
+    assert_eq!(0, md.purge_deleted(Duration::from_secs(3600)).unwrap());
+    assert_eq!(1, md.purge_deleted(Duration::from_secs(0)).unwrap());
+    assert_eq!(None, SoftVeggie::get(&md.include_deleted(), "garlic").unwrap());
+    assert!(SoftVeggie::find_by_flavor(&md.include_deleted(), "bold").unwrap().is_empty());
+    assert_eq!(None, SoftVeggie::undelete(&md, "garlic").unwrap());
+    assert_eq!(vec![Change::Deleted(garlic.clone())], changes.try_iter().collect::<Vec<_>>());
+
+    // the purge is logged as such, rather than as a second delete
+    let ops = md.audit_log(0).unwrap().iter().map(|e| e.op).collect::<Vec<AuditOp>>();
+    assert_eq!(vec![AuditOp::Put, AuditOp::Delete, AuditOp::Purge], ops);
+}
+
+#[test]
+fn history() {
+    let md = SoftVeggie::init(Db::new_temp().unwrap()).unwrap().with_history(Retention::keep_all()).unwrap();
+    let garlic = soft_veggie("garlic", "bold");
+    garlic.save(&md).unwrap();
+    sleep(Duration::from_millis(5));
+    let saved = SystemTime::now();
+    sleep(Duration::from_millis(5));
+    garlic.erase(&md).unwrap();
+    sleep(Duration::from_millis(5));
+    let erased = SystemTime::now();
+    sleep(Duration::from_millis(5));
+    SoftVeggie::undelete(&md, "garlic").unwrap();
+
+    // a tombstone is a change like any other, and the record didn't exist while it was there
+    let states = md.history("garlic").unwrap().into_iter().map(|r| r.value).collect::<Vec<_>>();
+    assert_eq!(vec![None, Some(garlic.clone()), None], states);
+    assert_eq!(Some(garlic.clone()), md.get_as_of("garlic", saved).unwrap());
+    assert_eq!(None, md.get_as_of("garlic", erased).unwrap());
+    assert_eq!(Some(garlic), md.get_as_of("garlic", SystemTime::now()).unwrap());
+}
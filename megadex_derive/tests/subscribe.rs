@@ -0,0 +1,32 @@
+
+use megadex_rkv::{ Change, Db, Field };
+
+mod common;
+
+use common::{ veggie, Veggie };
+
+#[test]
+fn subscribe_to_flavor() {
+    let md = Veggie::init(Db::new_temp().unwrap()).unwrap();
+    let bold = md.subscribe_to(Field::<Veggie, String>::new("flavor"), "bold").unwrap();
+    assert!(md.subscribe_to(Field::<Veggie, String>::new("color"), "red").is_err());
+
+    let garlic = veggie("garlic", "bold");
+    garlic.save(&md).unwrap();
+    veggie("rhubarb", "sour").save(&md).unwrap();
+
+    // garlic was bold before this change, so it is delivered, but not once it's gone for good
+    let mild = veggie("garlic", "mild");
+    mild.save(&md).unwrap();
+    mild.erase(&md).unwrap();
+
+    assert_eq!(Change::Inserted(garlic.clone()), bold.recv().unwrap());
+    assert_eq!(
+        Change::Updated {
+            old: garlic,
+            new: mild,
+        },
+        bold.recv().unwrap()
+    );
+    assert!(bold.try_recv().is_err());
+}
@@ -0,0 +1,74 @@
+
+use megadex_derive::Megadex;
+use megadex_rkv::{ Change, Db, Field, MegadexDb, MegadexDbError };
+use serde_derive::{ Serialize, Deserialize };
+use std::time::{ Duration, SystemTime };
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Megadex)]
+#[megadex(ttl_field = "expires_at")]
+pub struct Session {
+    #[id]
+    token: String,
+    #[indexed]
+    user: String,
+    expires_at: SystemTime,
+}
+
+fn session(token: &str, user: &str, ttl: i64) -> Session {
+    let now = SystemTime::now();
+    Session {
+        token: token.into(),
+        user: user.into(),
+        expires_at: if ttl < 0 { now - Duration::from_secs(-ttl as u64) } else { now + Duration::from_secs(ttl as u64) },
+    }
+}
+
+#[test]
+fn expired_sessions() {
+    let db = Db::new_temp().unwrap();
+    let md = Session::init(db.clone()).unwrap();
+    let changes = md.subscribe().unwrap();
+
+    let stale = session("a", "ann", -10);
+    let fresh = session("b", "ann", 3600);
+    stale.save(&md).unwrap();
+    fresh.save(&md).unwrap();
+
+    assert_eq!(None, Session::get(&md, "a").unwrap());
+    assert_eq!(vec![fresh.clone()], Session::find_by_user(&md, "ann").unwrap());
+    assert_eq!(vec!["b".to_string()], Session::id_by_user(&md, "ann").unwrap());
+    let ro = Session::init_read_only(db).unwrap();
+    assert_eq!(1, ro.get_by_field(Field::<Session, String>::new("user"), "ann").unwrap().len());
+
+    assert_eq!(1, md.purge_expired().unwrap());
+    assert_eq!(0, md.purge_expired().unwrap());
+    let purged: Vec<_> = changes.try_iter().collect();
+    assert_eq!(Some(&Change::Deleted(stale)), purged.last());
+
+    // the deadline moves along with the field on every save
+    let renewed = session("b", "ann", -1);
+    renewed.save(&md).unwrap();
+    assert_eq!(None, Session::get(&md, "b").unwrap());
+    fresh.save(&md).unwrap();
+    assert_eq!(Some(fresh), Session::get(&md, "b").unwrap());
+}
+
+#[test]
+fn purger() {
+    let md = Session::init(Db::new_temp().unwrap()).unwrap();
+    let purger = md.spawn_purger(Duration::from_millis(5)).unwrap();
+    session("a", "ann", 3600).save(&md).unwrap();
+    md.put_with_ttl(&"c".to_string(), &session("c", "cid", 3600), &[("user", &"cid".to_string())], Duration::from_millis(0)).unwrap();
+
+    for _ in 0..400 {
+        if purger.purged() == 1 {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(1, purger.purged());
+    assert_eq!(0, purger.failures());
+    drop(purger);
+    assert!(Session::find_by_user(&md, "cid").unwrap().is_empty());
+    assert!(Session::get(&md, "a").unwrap().is_some());
+}
@@ -0,0 +1,49 @@
+
+use megadex_derive::Megadex;
+use megadex_rkv::{ Db, MegadexDb, MegadexDbError };
+use serde_derive::{ Serialize, Deserialize };
+use std::thread;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Megadex)]
+pub struct Veggie {
+    #[id]
+    name: String,
+    #[indexed]
+    flavor: String,
+    count: u32,
+}
+
+#[test]
+fn update_in_place() {
+    let md = Veggie::init(Db::new_temp().unwrap()).unwrap();
+    let garlic = Veggie {
+        name: "garlic".into(),
+        flavor: "bold".into(),
+        count: 0,
+    };
+    garlic.save(&md).unwrap();
+
+    let updated = Veggie::update(&md, "garlic", |v| v.flavor = "mild".into()).unwrap().unwrap();
+    assert_eq!("mild", updated.flavor);
+    assert!(Veggie::find_by_flavor(&md, "bold").unwrap().is_empty());
+    assert_eq!(vec![updated], Veggie::find_by_flavor(&md, "mild").unwrap());
+
+    assert_eq!(None, Veggie::update(&md, "leek", |v| v.count += 1).unwrap());
+    assert!(Veggie::update(&md, "garlic", |v| v.name = "leek".into()).is_err());
+
+    // concurrent increments don't get lost
+    let workers: Vec<_> = (0..4)
+        .map(|_| {
+            let md = md.clone();
+            thread::spawn(move || {
+                for _ in 0..25 {
+                    Veggie::update(&md, "garlic", |v| v.count += 1).unwrap();
+                }
+            })
+        })
+        .collect();
+    for w in workers {
+        w.join().unwrap();
+    }
+    assert_eq!(100, Veggie::get(&md, "garlic").unwrap().unwrap().count);
+}
@@ -0,0 +1,56 @@
+
+use megadex_derive::Megadex;
+use megadex_rkv::{ Db, MegadexDb, MegadexDbError };
+use serde_derive::{ Serialize, Deserialize };
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Megadex)]
+pub struct Veggie {
+    #[id]
+    name: String,
+    #[indexed]
+    flavor: String,
+    #[version]
+    version: u64,
+}
+
+#[test]
+fn lost_updates() {
+    let db = Db::new_temp().unwrap();
+    let md = Veggie::init(db.clone()).unwrap();
+
+    let mut garlic = Veggie {
+        name: "garlic".into(),
+        flavor: "bold".into(),
+        version: 0,
+    };
+    garlic.save(&md).unwrap();
+    assert_eq!(1, garlic.version);
+
+    // two workers read the same record
+    let mut first = Veggie::get(&md, "garlic").unwrap().unwrap();
+    let mut second = Veggie::get(&md, "garlic").unwrap().unwrap();
+    assert_eq!(garlic, first);
+
+    first.flavor = "pungent".into();
+    first.save(&md).unwrap();
+    assert_eq!(2, first.version);
+
+    second.flavor = "mild".into();
+    assert_eq!(Err(MegadexDbError::VersionConflict(1, 2)), second.save(&md));
+    assert_eq!(1, second.version);
+
+    // the loser rereads and tries again, moving the index entry along
+    let mut second = Veggie::get(&md, "garlic").unwrap().unwrap();
+    second.flavor = "mild".into();
+    second.save(&md).unwrap();
+    assert!(Veggie::find_by_flavor(&md, "pungent").unwrap().is_empty());
+    assert_eq!(vec![second.clone()], Veggie::find_by_flavor(&md, "mild").unwrap());
+
+    // a plain insert overwrites, and still bumps the version
+    Veggie::insert(&md, &"garlic".into(), &garlic).unwrap();
+    assert_eq!(4, Veggie::get(&md, "garlic").unwrap().unwrap().version);
+    let updated = Veggie::update(&md, "garlic", |v| v.flavor = "sweet".into()).unwrap().unwrap();
+    assert_eq!(5, updated.version);
+    let ro = Veggie::init_read_only(db).unwrap();
+    assert_eq!(Some(5), ro.version(&"garlic".to_string()).unwrap());
+}
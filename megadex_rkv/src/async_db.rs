@@ -0,0 +1,311 @@
+use crate::auto_id::{
+    AutoId,
+    AutoRecord,
+};
+use crate::backend::Backend;
+use crate::codec::{
+    Bincode,
+    Codec,
+};
+use crate::db::Db;
+use crate::error::MegadexDbError;
+use crate::expiry;
+use crate::record::{
+    Field,
+    Record,
+};
+use crate::MegadexDb;
+use futures_core::Stream;
+use serde::{
+    de::DeserializeOwned,
+    Serialize,
+};
+use std::any::Any;
+use std::borrow::Borrow;
+use std::panic::{
+    self,
+    AssertUnwindSafe,
+};
+use std::pin::Pin;
+use std::sync::mpsc;
+use std::task::{
+    Context,
+    Poll,
+};
+use std::thread;
+use tokio::sync::{
+    mpsc as channel,
+    oneshot,
+};
+
+/// The number of records a stream reads per transaction
+const STREAM_PAGE: usize = 100;
+
+type Job<T, C, B> = Box<dyn FnOnce(&MegadexDb<T, C, B>) + Send>;
+
+/// A `MegadexDb` for async code, which must run inside a tokio runtime.
+///
+/// Reads run on tokio's blocking thread pool. Writes are handed to a writer thread of their own,
+/// which runs them one at a time, as LMDB would anyway. The writer thread stops when the last
+/// clone of the handle is dropped.
+pub struct AsyncMegadexDb<T, C = Bincode, B: Backend = Db> {
+    md: MegadexDb<T, C, B>,
+    writer: mpsc::Sender<Job<T, C, B>>,
+}
+
+impl<T, C, B: Backend> Clone for AsyncMegadexDb<T, C, B> {
+    fn clone(&self) -> Self {
+        AsyncMegadexDb {
+            md: self.md.clone(),
+            writer: self.writer.clone(),
+        }
+    }
+}
+
+/// The records of a query, read in pages as the stream is polled
+pub struct RecordStream<T> {
+    records: channel::Receiver<Result<T, MegadexDbError>>,
+}
+
+impl<T> RecordStream<T> {
+    /// The next record, or `None` at the end of the stream
+    pub async fn next(&mut self) -> Option<Result<T, MegadexDbError>> {
+        self.records.recv().await
+    }
+}
+
+impl<T> Stream for RecordStream<T> {
+    type Item = Result<T, MegadexDbError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.records.poll_recv(cx)
+    }
+}
+
+/// The message a write panicked with, for the error handed back to its caller
+fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    match panic.downcast::<String>() {
+        Ok(msg) => *msg,
+        Err(panic) => panic.downcast_ref::<&str>().copied().unwrap_or("unknown cause").to_string(),
+    }
+}
+
+async fn blocking<R, F>(f: F) -> Result<R, MegadexDbError>
+where
+    F: FnOnce() -> Result<R, MegadexDbError> + Send + 'static,
+    R: Send + 'static,
+{
+    tokio::task::spawn_blocking(f).await.map_err(|e| MegadexDbError::AsyncError(format!("{}", e)))?
+}
+
+impl<T, C, B> AsyncMegadexDb<T, C, B>
+where
+    T: Record + Serialize + DeserializeOwned + Send + Sync + 'static,
+    C: Codec + Send + Sync + 'static,
+    B: Backend,
+{
+    /// Take over `md`, starting its writer thread
+    pub fn new(md: MegadexDb<T, C, B>) -> Result<Self, MegadexDbError> {
+        let (writer, jobs) = mpsc::channel::<Job<T, C, B>>();
+        let shared = md.clone();
+        thread::Builder::new().name("megadex-writer".into()).spawn(move || {
+            for job in jobs {
+                job(&shared);
+            }
+        })?;
+        Ok(AsyncMegadexDb {
+            md,
+            writer,
+        })
+    }
+
+    /// See `MegadexDb::get`
+    pub async fn get<K>(&self, id: &K) -> Result<Option<T>, MegadexDbError>
+    where
+        T::Id: Borrow<K>,
+        K: Serialize + ?Sized,
+    {
+        let id = C::encode(id)?;
+        let md = self.md.clone();
+        blocking(move || md.db.read(|txn| md.get_live_txn(txn, &id))).await
+    }
+
+    /// See `MegadexDb::get_by_field`
+    pub async fn get_by_field<F, K>(&self, field: Field<T, F>, key: &K) -> Result<Vec<T>, MegadexDbError>
+    where
+        F: Borrow<K>,
+        K: Serialize + ?Sized,
+    {
+        let (name, key) = (field.name(), C::encode(key)?);
+        let md = self.md.clone();
+        blocking(move || md.get_by_key(name, &key)).await
+    }
+
+    /// Store `obj` under its own id, replacing an existing record along with its index entries.
+    /// Returns the replaced record.
+    pub async fn put(&self, obj: T) -> Result<Option<T>, MegadexDbError> {
+        self.write(move |md| md.put_record(&obj)).await
+    }
+
+    /// Store `obj` under its own id as long as the stored record is still at version `expected`,
+    /// see `MegadexDb::put_record_if_version`. Returns the new version.
+    pub async fn put_if_version(&self, obj: T, expected: u64) -> Result<u64, MegadexDbError> {
+        self.write(move |md| md.put_record_if_version(&obj, expected)).await
+    }
+
+    /// Give `obj` the next id and store it, see `MegadexDb::insert_new`. Returns the new id.
+    pub async fn insert_new(&self, mut obj: T) -> Result<T::Id, MegadexDbError>
+    where
+        T: AutoRecord,
+        T::Id: AutoId + Send + 'static,
+    {
+        self.write(move |md| md.insert_new(&mut obj)).await
+    }
+
+    /// See `MegadexDb::update`
+    pub async fn update<K, F>(&self, id: &K, f: F) -> Result<Option<T>, MegadexDbError>
+    where
+        T::Id: Borrow<K>,
+        K: Serialize + ?Sized,
+        F: FnMut(&mut T) + Send + 'static,
+    {
+        let id = C::encode(id)?;
+        self.write(move |md| md.update_record(&id, f)).await
+    }
+
+    /// Delete the record with the given id and its index entries. Returns the deleted record.
+    pub async fn del<K>(&self, id: &K) -> Result<Option<T>, MegadexDbError>
+    where
+        T::Id: Borrow<K>,
+        K: Serialize + ?Sized,
+    {
+        let id = C::encode(id)?;
+        self.write(move |md| md.del_record(&id)).await
+    }
+
+    /// Stream every record of the collection in id order. Each page of records is read in
+    /// a transaction of its own, so writes made while streaming may or may not show up.
+    pub fn stream(&self) -> RecordStream<T> {
+        let mut from: Option<Vec<u8>> = None;
+        self.pages(move |md| {
+            let mut page = Vec::new();
+            let now = expiry::now();
+            md.db.read(|txn| {
+                txn.scan(md.main, from.as_ref().map(|f| &f[..]), &mut |id, blob| {
+                    if from.as_ref().map(|f| &f[..]) != Some(id) {
+                        let obj = if md.hidden(txn, id, now)? { None } else { Some(md.decode_value(id, blob)?) };
+                        page.push((id.to_vec(), obj));
+                    }
+                    Ok(page.len() < STREAM_PAGE)
+                })
+            })?;
+            from = page.last().map(|(id, _)| id.clone());
+            if page.is_empty() {
+                return Ok(None);
+            }
+            Ok(Some(page.into_iter().filter_map(|(_, obj)| obj).collect()))
+        })
+    }
+
+    /// Stream the records that are indexed by the provided field. The ids are looked up up front,
+    /// records which are deleted before their page is read are skipped.
+    pub fn stream_by_field<F, K>(&self, field: Field<T, F>, key: &K) -> Result<RecordStream<T>, MegadexDbError>
+    where
+        F: Borrow<K>,
+        K: Serialize + ?Sized,
+    {
+        let index = self.md.index(field.name())?;
+        let key = C::encode(key)?;
+        let mut ids: Option<Vec<Vec<u8>>> = None;
+        Ok(self.pages(move |md| {
+            md.db.read(|txn| {
+                let ids = match ids {
+                    Some(ref mut ids) => ids,
+                    None => {
+                        let mut all = txn.get_dup(index, &key)?;
+                        all.reverse();
+                        ids.get_or_insert(all)
+                    },
+                };
+                if ids.is_empty() {
+                    return Ok(None);
+                }
+                let mut page = Vec::new();
+                while page.len() < STREAM_PAGE {
+                    match ids.pop() {
+                        Some(id) => page.extend(md.get_live_txn(txn, &id)?),
+                        None => break,
+                    }
+                }
+                Ok(Some(page))
+            })
+        }))
+    }
+
+    /// Feed the pages returned by `next_page` to a stream until it returns `None`
+    fn pages<F>(&self, next_page: F) -> RecordStream<T>
+    where
+        F: FnMut(&MegadexDb<T, C, B>) -> Result<Option<Vec<T>>, MegadexDbError> + Send + 'static,
+    {
+        let (sender, records) = channel::channel(STREAM_PAGE);
+        let md = self.md.clone();
+        tokio::spawn(async move {
+            let mut next_page = next_page;
+            loop {
+                let shared = md.clone();
+                let res = blocking(move || {
+                    let page = next_page(&shared);
+                    Ok((next_page, page))
+                })
+                .await;
+                let page = match res {
+                    Ok((f, page)) => {
+                        next_page = f;
+                        page
+                    },
+                    Err(e) => {
+                        let _ = sender.send(Err(e)).await;
+                        return;
+                    },
+                };
+                match page {
+                    Ok(None) => return,
+                    Ok(Some(page)) => {
+                        for obj in page {
+                            if sender.send(Ok(obj)).await.is_err() {
+                                return;
+                            }
+                        }
+                    },
+                    Err(e) => {
+                        let _ = sender.send(Err(e)).await;
+                        return;
+                    },
+                }
+            }
+        });
+        RecordStream {
+            records,
+        }
+    }
+
+    /// Run `f` on the writer thread
+    async fn write<R, F>(&self, f: F) -> Result<R, MegadexDbError>
+    where
+        F: FnOnce(&MegadexDb<T, C, B>) -> Result<R, MegadexDbError> + Send + 'static,
+        R: Send + 'static,
+    {
+        let (done, res) = oneshot::channel();
+        // a write that panics must not take the writer thread down with it. Its transaction is aborted
+        // as the panic unwinds, and the caller gets an error instead.
+        let job: Job<T, C, B> = Box::new(move |md| {
+            let res = panic::catch_unwind(AssertUnwindSafe(|| f(md))).unwrap_or_else(|panic| {
+                Err(MegadexDbError::AsyncError(format!("The write panicked: {}", panic_message(panic))))
+            });
+            let _ = done.send(res);
+        });
+        let stopped = || MegadexDbError::AsyncError("The writer thread has stopped".into());
+        self.writer.send(job).map_err(|_| stopped())?;
+        res.await.map_err(|_| stopped())?
+    }
+}
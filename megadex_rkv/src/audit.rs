@@ -0,0 +1,257 @@
+use crate::backend::{
+    Backend,
+    ReadTxn,
+    WriteTxn,
+};
+use crate::codec::Codec;
+use crate::error::MegadexDbError;
+use crate::expiry::{
+    self,
+    read_time,
+};
+use crate::internal::{
+    self,
+    Section,
+};
+use crate::MegadexDb;
+use serde::{
+    de::DeserializeOwned,
+    Serialize,
+};
+use sha2::{
+    Digest,
+    Sha256,
+};
+use std::time::{
+    Duration,
+    SystemTime,
+    UNIX_EPOCH,
+};
+
+const HEAD: &[u8] = b"head";
+
+/// The hash an audit log starts its chain from
+const GENESIS: [u8; 32] = [0; 32];
+
+/// The kind of mutation an audit entry records
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AuditOp {
+    Put,
+    Delete,
+    /// A soft deleted record was removed for good, see `MegadexDb::purge_deleted`
+    Purge,
+}
+
+/// An entry of the audit log, see `MegadexDb::audit_log`
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub op: AuditOp,
+    pub collection: String,
+    /// The encoded id of the record
+    pub id: Vec<u8>,
+    pub timestamp: SystemTime,
+    pub actor: String,
+    /// The SHA-256 of the previous entry's hash and this entry
+    pub hash: [u8; 32],
+}
+
+/// Append `field` to `buf`, prefixed with its length
+fn push_field(buf: &mut Vec<u8>, field: &[u8]) {
+    buf.extend_from_slice(&(field.len() as u32).to_be_bytes());
+    buf.extend_from_slice(field);
+}
+
+/// Split a field written by `push_field` off the front of `buf`
+fn take_field<'b>(buf: &mut &'b [u8]) -> Result<&'b [u8], MegadexDbError> {
+    if buf.len() < 4 {
+        return Err(malformed());
+    }
+    let mut len = [0; 4];
+    len.copy_from_slice(&buf[..4]);
+    let len = u32::from_be_bytes(len) as usize;
+    if buf.len() < 4 + len {
+        return Err(malformed());
+    }
+    let field = &buf[4..4 + len];
+    *buf = &buf[4 + len..];
+    Ok(field)
+}
+
+fn malformed() -> MegadexDbError {
+    MegadexDbError::ValueError("Malformed audit entry".into())
+}
+
+fn chain(prev: &[u8; 32], seq: u64, body: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(prev);
+    hasher.update(seq.to_be_bytes());
+    hasher.update(body);
+    hasher.finalize().into()
+}
+
+impl AuditEntry {
+    /// The part of a stored entry which is hashed, everything but the sequence number and the hash
+    fn body(&self) -> Vec<u8> {
+        let mut body = vec![match self.op {
+            AuditOp::Put => b'p',
+            AuditOp::Delete => b'd',
+            AuditOp::Purge => b'x',
+        }];
+        body.extend_from_slice(&expiry::millis(self.timestamp).to_be_bytes());
+        push_field(&mut body, self.collection.as_bytes());
+        push_field(&mut body, &self.id);
+        push_field(&mut body, self.actor.as_bytes());
+        body
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut value = self.hash.to_vec();
+        value.extend(self.body());
+        value
+    }
+
+    fn decode(key: &[u8], value: &[u8]) -> Result<AuditEntry, MegadexDbError> {
+        if value.len() < 41 {
+            return Err(malformed());
+        }
+        let mut hash = [0; 32];
+        hash.copy_from_slice(&value[..32]);
+        let op = match value[32] {
+            b'p' => AuditOp::Put,
+            b'd' => AuditOp::Delete,
+            b'x' => AuditOp::Purge,
+            _ => return Err(malformed()),
+        };
+        let timestamp = UNIX_EPOCH + Duration::from_millis(read_time(&value[33..])?);
+        let mut rest = &value[41..];
+        let text = |field: &[u8]| String::from_utf8(field.to_vec()).map_err(|_| malformed());
+        let collection = text(take_field(&mut rest)?)?;
+        let id = take_field(&mut rest)?.to_vec();
+        let actor = text(take_field(&mut rest)?)?;
+        Ok(AuditEntry {
+            seq: read_time(key)?,
+            op,
+            collection,
+            id,
+            timestamp,
+            actor,
+            hash,
+        })
+    }
+}
+
+/// The sequence number and hash of the last entry of the log
+fn head<B, X>(txn: &X, section: Section<B::Single>) -> Result<Option<(u64, [u8; 32])>, MegadexDbError>
+where
+    B: Backend,
+    X: ReadTxn<B> + ?Sized,
+{
+    match section.get(txn, HEAD)? {
+        Some(head) if head.len() == 40 => {
+            let mut hash = [0; 32];
+            hash.copy_from_slice(&head[8..]);
+            Ok(Some((read_time(&head)?, hash)))
+        },
+        Some(_) => Err(malformed()),
+        None => Ok(None),
+    }
+}
+
+fn not_enabled() -> MegadexDbError {
+    MegadexDbError::ValueError("Auditing is not enabled for this collection".into())
+}
+
+impl<T, C, B> MegadexDb<T, C, B>
+where
+    T: Serialize + DeserializeOwned,
+    C: Codec,
+    B: Backend,
+{
+    /// Append an entry to an audit log for every put and delete, in the same transaction.
+    /// The entries name this collection `collection`.
+    pub fn with_audit(self, collection: &str) -> Result<Self, MegadexDbError> {
+        self.open_audit(collection, true)
+    }
+
+    pub(crate) fn open_audit(mut self, collection: &str, create: bool) -> Result<Self, MegadexDbError> {
+        self.audit = Some((Section::open(&self.db, internal::AUDIT, create)?, collection.into()));
+        Ok(self)
+    }
+
+    /// A handle on the same collection whose writes are recorded in the audit log under `actor`
+    pub fn as_actor(&self, actor: &str) -> Self {
+        let mut md = self.clone();
+        md.actor = actor.into();
+        md
+    }
+
+    /// The entries of the audit log, starting with sequence number `from`
+    pub fn audit_log(&self, from: u64) -> Result<Vec<AuditEntry>, MegadexDbError> {
+        let (section, _) = self.audit.clone().ok_or_else(not_enabled)?;
+        let mut entries = Vec::new();
+        self.db.read(|txn| {
+            section.scan(txn, Some(&from.to_be_bytes()[..]), &mut |key, value| {
+                if key.len() == 8 {
+                    entries.push(AuditEntry::decode(key, value)?);
+                }
+                Ok(true)
+            })
+        })?;
+        Ok(entries)
+    }
+
+    /// Check that every entry of the audit log is in sequence and hashes to its recorded hash,
+    /// chained to the one before it. Returns the number of entries, or fails with `AuditBroken`
+    /// naming the first entry that doesn't check out.
+    pub fn verify_audit(&self) -> Result<u64, MegadexDbError> {
+        let (section, _) = self.audit.clone().ok_or_else(not_enabled)?;
+        self.db.read(|txn| {
+            let mut count = 0;
+            let mut prev = GENESIS;
+            section.scan(txn, None, &mut |key, value| {
+                if key.len() != 8 {
+                    return Ok(true);
+                }
+                let entry = AuditEntry::decode(key, value).map_err(|_| MegadexDbError::AuditBroken(count))?;
+                if entry.seq != count || chain(&prev, entry.seq, &entry.body()) != entry.hash {
+                    return Err(MegadexDbError::AuditBroken(count));
+                }
+                prev = entry.hash;
+                count += 1;
+                Ok(true)
+            })?;
+            match head(txn, section)? {
+                None if count == 0 => Ok(0),
+                Some((last, hash)) if last + 1 == count && hash == prev => Ok(count),
+                _ => Err(MegadexDbError::AuditBroken(count)),
+            }
+        })
+    }
+
+    /// Record a mutation of the record with the encoded `id` within an existing transaction
+    pub(crate) fn audit_txn(&self, txn: &mut dyn WriteTxn<B>, op: AuditOp, id: &[u8]) -> Result<(), MegadexDbError> {
+        let (section, collection) = match self.audit {
+            Some((section, ref collection)) => (section, collection),
+            None => return Ok(()),
+        };
+        let (seq, prev) = match head(&*txn, section)? {
+            Some((last, hash)) => (last + 1, hash),
+            None => (0, GENESIS),
+        };
+        let mut entry = AuditEntry {
+            seq,
+            op,
+            collection: collection.to_string(),
+            id: id.to_vec(),
+            timestamp: UNIX_EPOCH + Duration::from_millis(expiry::now()),
+            actor: self.actor.to_string(),
+            hash: GENESIS,
+        };
+        entry.hash = chain(&prev, seq, &entry.body());
+        section.put(txn, &seq.to_be_bytes(), &entry.encode())?;
+        let mut head = seq.to_be_bytes().to_vec();
+        head.extend_from_slice(&entry.hash);
+        section.put(txn, HEAD, &head)
+    }
+}
@@ -0,0 +1,202 @@
+use crate::backend::Backend;
+use crate::codec::Codec;
+use crate::error::MegadexDbError;
+use crate::expiry;
+use crate::internal::{
+    self,
+    Section,
+};
+use crate::record::Record;
+use crate::subscribe::Change;
+use crate::MegadexDb;
+use serde::{
+    de::{
+        self,
+        DeserializeOwned,
+    },
+    Deserialize,
+    Deserializer,
+    Serialize,
+    Serializer,
+};
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{
+    BuildHasher,
+    Hasher,
+};
+use std::str::FromStr;
+use std::time::{
+    Duration,
+    SystemTime,
+    UNIX_EPOCH,
+};
+
+const LAST: &[u8] = b"last";
+
+/// Crockford's base32, which leaves out I, L, O and U
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// The number of random bits at the end of a `Ulid`
+const RANDOM_BITS: u32 = 80;
+
+/// An id type which `MegadexDb::insert_new` can make up
+pub trait AutoId: Serialize + DeserializeOwned + Clone {
+    /// The id to give the next new record, after `last` if ids were made up before.
+    /// Fails if there is no id after `last`.
+    fn next(last: Option<&Self>) -> Result<Self, MegadexDbError>;
+}
+
+fn exhausted() -> MegadexDbError {
+    MegadexDbError::ValueError("There are no ids left to make up".into())
+}
+
+/// A sequence number which counts up from 1
+impl AutoId for u64 {
+    fn next(last: Option<&u64>) -> Result<u64, MegadexDbError> {
+        match last {
+            Some(n) => n.checked_add(1).ok_or_else(exhausted),
+            None => Ok(1),
+        }
+    }
+}
+
+/// A fresh `Ulid`, or the one after `last` if that is later, e.g. when the clock stepped back
+impl AutoId for Ulid {
+    fn next(last: Option<&Ulid>) -> Result<Ulid, MegadexDbError> {
+        let fresh = Ulid::new();
+        match last {
+            Some(last) if fresh <= *last => last.0.checked_add(1).map(Ulid).ok_or_else(exhausted),
+            _ => Ok(fresh),
+        }
+    }
+}
+
+/// A record whose id `MegadexDb::insert_new` makes up. `#[id(auto)]` implements this.
+pub trait AutoRecord: Record {
+    /// Replace the id of this record
+    fn set_id(&mut self, id: Self::Id);
+}
+
+/// A time ordered id: 48 bits of milliseconds since the epoch followed by 80 random bits.
+/// It is stored as its 26 character base32 string, so ids sort in the order they were made.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Ulid(pub u128);
+
+impl Ulid {
+    /// A new id for the current time
+    pub fn new() -> Ulid {
+        let mut random = 0u128;
+        for _ in 0..2 {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u128(random);
+            random = random << 64 | hasher.finish() as u128;
+        }
+        let mask = (1 << RANDOM_BITS) - 1;
+        Ulid((expiry::now() as u128) << RANDOM_BITS | random & mask)
+    }
+
+    /// The time this id was made at, to the millisecond
+    pub fn timestamp(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis((self.0 >> RANDOM_BITS) as u64)
+    }
+}
+
+impl Default for Ulid {
+    fn default() -> Ulid {
+        Ulid::new()
+    }
+}
+
+impl fmt::Display for Ulid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut text = [0u8; 26];
+        for (i, c) in text.iter_mut().enumerate() {
+            *c = ALPHABET[(self.0 >> (125 - 5 * i)) as usize & 31];
+        }
+        f.write_str(std::str::from_utf8(&text).expect("base32 is ascii"))
+    }
+}
+
+impl FromStr for Ulid {
+    type Err = MegadexDbError;
+
+    fn from_str(text: &str) -> Result<Ulid, MegadexDbError> {
+        let invalid = || MegadexDbError::ValueError(format!("Invalid ULID {}", text));
+        // the first character only holds the top 3 bits
+        if text.len() != 26 || text.as_bytes()[0] > b'7' {
+            return Err(invalid());
+        }
+        let mut n = 0u128;
+        for c in text.bytes() {
+            let digit = ALPHABET.iter().position(|a| *a == c.to_ascii_uppercase()).ok_or_else(invalid)?;
+            n = n << 5 | digit as u128;
+        }
+        Ok(Ulid(n))
+    }
+}
+
+impl Serialize for Ulid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Ulid {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Ulid, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+fn not_enabled() -> MegadexDbError {
+    MegadexDbError::ValueError("Auto ids are not enabled for this collection".into())
+}
+
+impl<T, C, B> MegadexDb<T, C, B>
+where
+    T: Serialize + DeserializeOwned,
+    C: Codec,
+    B: Backend,
+{
+    /// Keep track of the last id `insert_new` made up, so ids aren't handed out twice
+    pub fn with_auto_id(mut self) -> Result<Self, MegadexDbError> {
+        self.sequence = Some(Section::open(&self.db, internal::SEQUENCE, true)?);
+        Ok(self)
+    }
+}
+
+impl<T, C, B> MegadexDb<T, C, B>
+where
+    T: AutoRecord + Serialize + DeserializeOwned,
+    T::Id: AutoId,
+    C: Codec,
+    B: Backend,
+{
+    /// Give `obj` the next id and store it, in one write transaction. Ids which are
+    /// taken already, e.g. by records that were stored under an id of their own, are skipped.
+    /// Returns the new id.
+    pub fn insert_new(&self, obj: &mut T) -> Result<T::Id, MegadexDbError> {
+        let section = self.sequence.ok_or_else(not_enabled)?;
+        let id = self.db.write(|txn| {
+            let last = match section.get(&*txn, LAST)? {
+                Some(last) => Some(C::decode(&last)?),
+                None => None,
+            };
+            let mut id = T::Id::next(last.as_ref())?;
+            let mut keybytes = C::encode(&id)?;
+            while txn.get(self.main, &keybytes)?.is_some() {
+                id = T::Id::next(Some(&id))?;
+                keybytes = C::encode(&id)?;
+            }
+            section.put(txn, LAST, &keybytes)?;
+            obj.set_id(id.clone());
+            self.put_record_txn(txn, obj)?;
+            Ok(id)
+        })?;
+        if let Some(stamp) = self.versions {
+            stamp(obj, 1);
+        }
+        self.subscribers.notify(&[Change::Inserted(obj)])?;
+        Ok(id)
+    }
+}
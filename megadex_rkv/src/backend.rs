@@ -0,0 +1,74 @@
+use crate::error::MegadexDbError;
+use crate::stats::PageStats;
+
+/// A storage engine that `MegadexDb` can keep its collections in.
+///
+/// A backend provides named single-value stores (one value per key) and multi-value stores
+/// (a sorted set of values per key), and runs closures against them inside read or write transactions.
+/// A write transaction is committed when its closure returns `Ok` and rolled back when it returns `Err`.
+///
+/// Two backends are provided: `Db`, which persists to LMDB through rkv, and `MemDb`,
+/// which keeps everything in memory.
+pub trait Backend: Clone + Send + Sync + 'static {
+    /// Handle to a store with a single value per key
+    type Single: Copy + Send + Sync;
+    /// Handle to a store with a sorted set of values per key
+    type Multi: Copy + Send + Sync;
+
+    /// Open the named single-value store. If it doesn't exist it is created if `create` is set,
+    /// otherwise this fails with `StoreNotFound`.
+    fn open_single(&self, name: &str, create: bool) -> Result<Self::Single, MegadexDbError>;
+
+    /// Open the named multi-value store, see `open_single`
+    fn open_multi(&self, name: &str, create: bool) -> Result<Self::Multi, MegadexDbError>;
+
+    /// Run `f` inside a read transaction
+    fn read<R, F>(&self, f: F) -> Result<R, MegadexDbError>
+    where
+        F: FnOnce(&dyn ReadTxn<Self>) -> Result<R, MegadexDbError>;
+
+    /// Run `f` inside a write transaction.
+    /// A backend may run `f` again in a fresh transaction if the first attempt failed for lack of space.
+    fn write<R, F>(&self, f: F) -> Result<R, MegadexDbError>
+    where
+        F: FnMut(&mut dyn WriteTxn<Self>) -> Result<R, MegadexDbError>;
+
+    /// The B-tree statistics of the named store, if the backend keeps any
+    fn page_stats(&self, _name: &str) -> Result<Option<PageStats>, MegadexDbError> {
+        Ok(None)
+    }
+}
+
+/// The callback type used to visit the entries of a store. Return `Ok(false)` to stop early.
+pub type Visitor<'v> = dyn FnMut(&[u8], &[u8]) -> Result<bool, MegadexDbError> + 'v;
+
+/// Read operations of a transaction
+pub trait ReadTxn<B: Backend> {
+    /// Fetch the value stored under `key`
+    fn get(&self, store: B::Single, key: &[u8]) -> Result<Option<Vec<u8>>, MegadexDbError>;
+
+    /// Fetch all values stored under `key`, in order
+    fn get_dup(&self, store: B::Multi, key: &[u8]) -> Result<Vec<Vec<u8>>, MegadexDbError>;
+
+    /// Visit the entries of a single-value store in key order, beginning with the first
+    /// key which is greater than or equal to `from`.
+    fn scan(&self, store: B::Single, from: Option<&[u8]>, f: &mut Visitor) -> Result<(), MegadexDbError>;
+
+    /// Visit every key and value of a multi-value store in order
+    fn scan_dup(&self, store: B::Multi, f: &mut Visitor) -> Result<(), MegadexDbError>;
+}
+
+/// Write operations of a transaction
+pub trait WriteTxn<B: Backend>: ReadTxn<B> {
+    /// Store `value` under `key`, replacing any existing value
+    fn put(&mut self, store: B::Single, key: &[u8], value: &[u8]) -> Result<(), MegadexDbError>;
+
+    /// Remove `key`, returning whether it was present
+    fn delete(&mut self, store: B::Single, key: &[u8]) -> Result<bool, MegadexDbError>;
+
+    /// Add `value` to the set of values under `key`
+    fn put_dup(&mut self, store: B::Multi, key: &[u8], value: &[u8]) -> Result<(), MegadexDbError>;
+
+    /// Remove `value` from the set of values under `key`, returning whether it was present
+    fn delete_dup(&mut self, store: B::Multi, key: &[u8], value: &[u8]) -> Result<bool, MegadexDbError>;
+}
@@ -0,0 +1,74 @@
+use crate::error::MegadexDbError;
+use serde::{
+    de::DeserializeOwned,
+    Serialize,
+};
+
+/// The serialization format used to turn ids, index keys and values into the bytes
+/// that are written to the underlying store.
+///
+/// Implementations are zero-sized marker types which are selected through the
+/// `C` type parameter of `MegadexDb<T, C>`.
+pub trait Codec {
+    fn encode<S: Serialize + ?Sized>(val: &S) -> Result<Vec<u8>, MegadexDbError>;
+    fn decode<D: DeserializeOwned>(bytes: &[u8]) -> Result<D, MegadexDbError>;
+}
+
+/// The default codec. Compact, but not self-describing
+pub struct Bincode;
+
+impl Codec for Bincode {
+    fn encode<S: Serialize + ?Sized>(val: &S) -> Result<Vec<u8>, MegadexDbError> {
+        bincode::serialize(val).map_err(MegadexDbError::from)
+    }
+
+    fn decode<D: DeserializeOwned>(bytes: &[u8]) -> Result<D, MegadexDbError> {
+        bincode::deserialize(bytes).map_err(MegadexDbError::from)
+    }
+}
+
+/// Stores everything as JSON, which makes the records readable from other tools
+#[cfg(feature = "json")]
+pub struct Json;
+
+#[cfg(feature = "json")]
+impl Codec for Json {
+    fn encode<S: Serialize + ?Sized>(val: &S) -> Result<Vec<u8>, MegadexDbError> {
+        serde_json::to_vec(val).map_err(|e| MegadexDbError::CodecError(format!("{}", e)))
+    }
+
+    fn decode<D: DeserializeOwned>(bytes: &[u8]) -> Result<D, MegadexDbError> {
+        serde_json::from_slice(bytes).map_err(|e| MegadexDbError::CodecError(format!("{}", e)))
+    }
+}
+
+/// Stores everything as CBOR (RFC 7049)
+#[cfg(feature = "cbor")]
+pub struct Cbor;
+
+#[cfg(feature = "cbor")]
+impl Codec for Cbor {
+    fn encode<S: Serialize + ?Sized>(val: &S) -> Result<Vec<u8>, MegadexDbError> {
+        serde_cbor::to_vec(&val).map_err(|e| MegadexDbError::CodecError(format!("{}", e)))
+    }
+
+    fn decode<D: DeserializeOwned>(bytes: &[u8]) -> Result<D, MegadexDbError> {
+        serde_cbor::from_slice(bytes).map_err(|e| MegadexDbError::CodecError(format!("{}", e)))
+    }
+}
+
+/// Stores everything as MessagePack. Structs are written as maps so that
+/// field names survive reordering of the struct definition.
+#[cfg(feature = "msgpack")]
+pub struct MsgPack;
+
+#[cfg(feature = "msgpack")]
+impl Codec for MsgPack {
+    fn encode<S: Serialize + ?Sized>(val: &S) -> Result<Vec<u8>, MegadexDbError> {
+        rmp_serde::to_vec_named(val).map_err(|e| MegadexDbError::CodecError(format!("{}", e)))
+    }
+
+    fn decode<D: DeserializeOwned>(bytes: &[u8]) -> Result<D, MegadexDbError> {
+        rmp_serde::from_slice(bytes).map_err(|e| MegadexDbError::CodecError(format!("{}", e)))
+    }
+}
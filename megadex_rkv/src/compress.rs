@@ -0,0 +1,141 @@
+use crate::error::MegadexDbError;
+#[cfg(feature = "zstd")]
+use std::io::{
+    Read,
+    Write,
+};
+#[cfg(feature = "zstd")]
+use std::sync::Arc;
+
+/// Header byte for a record which is stored as is
+const RAW: u8 = 0;
+/// Header byte for a zstd frame
+#[cfg(feature = "zstd")]
+const ZSTD: u8 = 1;
+/// Header byte for a zstd frame which was compressed with the collection's dictionary
+#[cfg(feature = "zstd")]
+const ZSTD_DICT: u8 = 2;
+/// Header byte for an lz4 block with its uncompressed size prepended
+#[cfg(feature = "lz4")]
+const LZ4: u8 = 3;
+
+/// Compression applied to the blobs in the main store of a collection.
+///
+/// Every compressed blob starts with a byte which names the algorithm it was written with, so records
+/// written with different settings (or not compressed at all because it would not have saved space)
+/// can coexist in the same store.
+#[derive(Clone, Debug)]
+pub enum Compression {
+    /// Write the header, but store the records uncompressed. This is useful to stop
+    /// compressing a collection while keeping the records which are already compressed readable.
+    Uncompressed,
+    /// zstd at the given level, optionally with a dictionary trained on
+    /// representative records, see `train_dictionary`
+    #[cfg(feature = "zstd")]
+    Zstd {
+        level: i32,
+        dictionary: Option<Arc<Vec<u8>>>,
+    },
+    #[cfg(feature = "lz4")]
+    Lz4,
+}
+
+impl Compression {
+    /// zstd with the default compression level
+    #[cfg(feature = "zstd")]
+    pub fn zstd() -> Compression {
+        Compression::Zstd {
+            level: 0,
+            dictionary: None,
+        }
+    }
+
+    /// zstd with a dictionary, see `train_dictionary`
+    #[cfg(feature = "zstd")]
+    pub fn zstd_with_dictionary(level: i32, dictionary: Vec<u8>) -> Compression {
+        Compression::Zstd {
+            level,
+            dictionary: Some(Arc::new(dictionary)),
+        }
+    }
+
+    /// Prepend the header and compress the blob.  If compressing doesn't make
+    /// the blob any smaller it is stored raw.
+    pub fn compress(&self, blob: Vec<u8>) -> Result<Vec<u8>, MegadexDbError> {
+        let packed: Option<Vec<u8>> = match *self {
+            Compression::Uncompressed => None,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd {
+                level,
+                ref dictionary,
+            } => {
+                let header = if dictionary.is_some() {
+                    ZSTD_DICT
+                } else {
+                    ZSTD
+                };
+                let dictionary = dictionary.as_ref().map(|d| d.as_slice()).unwrap_or(&[]);
+                let mut encoder = zstd::stream::Encoder::with_dictionary(vec![header], level, dictionary)?;
+                encoder.write_all(&blob)?;
+                Some(encoder.finish()?)
+            },
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => {
+                let mut out = vec![LZ4];
+                out.extend(lz4::block::compress(&blob, None, true)?);
+                Some(out)
+            },
+        };
+        match packed {
+            Some(packed) if packed.len() <= blob.len() => Ok(packed),
+            _ => {
+                let mut out = Vec::with_capacity(blob.len() + 1);
+                out.push(RAW);
+                out.extend(blob);
+                Ok(out)
+            },
+        }
+    }
+
+    /// Inspect the header and decompress the blob accordingly.
+    pub fn decompress(&self, blob: &[u8]) -> Result<Vec<u8>, MegadexDbError> {
+        let (header, body) = blob
+            .split_first()
+            .ok_or_else(|| MegadexDbError::CompressionError("Missing compression header".into()))?;
+        match *header {
+            RAW => Ok(body.to_vec()),
+            #[cfg(feature = "zstd")]
+            ZSTD => {
+                let mut out = Vec::new();
+                zstd::stream::Decoder::new(body)?.read_to_end(&mut out)?;
+                Ok(out)
+            },
+            #[cfg(feature = "zstd")]
+            ZSTD_DICT => {
+                let dictionary = match *self {
+                    Compression::Zstd {
+                        dictionary: Some(ref d),
+                        ..
+                    } => d,
+                    _ => {
+                        return Err(MegadexDbError::CompressionError(
+                            "Record was compressed with a dictionary, but none is configured".into(),
+                        ))
+                    },
+                };
+                let mut out = Vec::new();
+                zstd::stream::Decoder::with_dictionary(body, dictionary)?.read_to_end(&mut out)?;
+                Ok(out)
+            },
+            #[cfg(feature = "lz4")]
+            LZ4 => lz4::block::decompress(body, None).map_err(|e| e.into()),
+            h => Err(MegadexDbError::CompressionError(format!("Unknown compression header {}", h))),
+        }
+    }
+}
+
+/// Train a zstd dictionary from a set of representative (uncompressed) records
+#[cfg(feature = "zstd")]
+pub fn train_dictionary<S: AsRef<[u8]>>(samples: &[S], max_size: usize) -> Result<Vec<u8>, MegadexDbError> {
+    zstd::dict::from_samples(samples, max_size).map_err(|e| e.into())
+}
@@ -0,0 +1,125 @@
+use crate::error::MegadexDbError;
+use chacha20poly1305::aead::{
+    Aead,
+    AeadCore,
+    KeyInit,
+    OsRng,
+    Payload,
+};
+use chacha20poly1305::{
+    ChaCha20Poly1305,
+    Key,
+    Nonce,
+};
+use std::collections::HashMap;
+
+/// Version byte of the envelope format, so that it can evolve
+const ENVELOPE_V1: u8 = 1;
+const KEY_ID_LEN: usize = 4;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = 1 + KEY_ID_LEN + NONCE_LEN;
+
+/// A 256 bit ChaCha20-Poly1305 key
+pub type EncryptionKey = [u8; 32];
+
+/// Supplies the keys used to encrypt and decrypt the records of a collection.
+///
+/// Every encrypted record carries the id of the key it was encrypted with, so a provider
+/// must be able to hand out retired keys until the collection has been rekeyed
+/// with `MegadexDb::rekey`.
+pub trait KeyProvider: Send + Sync {
+    /// The id and key that new records are encrypted with
+    fn current_key(&self) -> Result<(u32, EncryptionKey), MegadexDbError>;
+    /// Look up the key with the given id, returning `None` if it is unknown
+    fn key(&self, id: u32) -> Result<Option<EncryptionKey>, MegadexDbError>;
+}
+
+/// A `KeyProvider` holding its keys in memory
+#[derive(Clone)]
+pub struct StaticKeys {
+    current: u32,
+    keys: HashMap<u32, EncryptionKey>,
+}
+
+impl StaticKeys {
+    pub fn new(id: u32, key: EncryptionKey) -> StaticKeys {
+        let mut keys = HashMap::new();
+        keys.insert(id, key);
+        StaticKeys {
+            current: id,
+            keys,
+        }
+    }
+
+    /// Add a key and make it the one new records are encrypted with.
+    /// Previous keys stay available for decryption.
+    pub fn rotate(mut self, id: u32, key: EncryptionKey) -> StaticKeys {
+        self.keys.insert(id, key);
+        self.current = id;
+        self
+    }
+}
+
+impl KeyProvider for StaticKeys {
+    fn current_key(&self) -> Result<(u32, EncryptionKey), MegadexDbError> {
+        Ok((self.current, self.keys[&self.current]))
+    }
+
+    fn key(&self, id: u32) -> Result<Option<EncryptionKey>, MegadexDbError> {
+        Ok(self.keys.get(&id).cloned())
+    }
+}
+
+/// The additional authenticated data of a record, which binds the ciphertext
+/// to the envelope header and the id it is stored under
+fn aad(header: &[u8], id: &[u8]) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(header.len() + id.len());
+    aad.extend_from_slice(header);
+    aad.extend_from_slice(id);
+    aad
+}
+
+/// Encrypt a blob under the provider's current key.
+/// The envelope is laid out as `version | key id (u32 BE) | nonce | ciphertext`
+pub fn seal(keys: &dyn KeyProvider, id: &[u8], blob: &[u8]) -> Result<Vec<u8>, MegadexDbError> {
+    let (key_id, key) = keys.current_key()?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let mut out = Vec::with_capacity(HEADER_LEN + blob.len() + 16);
+    out.push(ENVELOPE_V1);
+    out.extend_from_slice(&key_id.to_be_bytes());
+    out.extend_from_slice(&nonce);
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload {
+            msg: blob,
+            aad: &aad(&out, id),
+        })
+        .map_err(|_| MegadexDbError::CryptoError("Encryption failed".into()))?;
+    out.extend(ciphertext);
+    Ok(out)
+}
+
+/// Decrypt a blob produced by `seal`
+pub fn open(keys: &dyn KeyProvider, id: &[u8], blob: &[u8]) -> Result<Vec<u8>, MegadexDbError> {
+    let key_id = key_id(blob)?;
+    let key = keys.key(key_id)?.ok_or_else(|| MegadexDbError::CryptoError(format!("Unknown key id {}", key_id)))?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    let (header, ciphertext) = blob.split_at(HEADER_LEN);
+    cipher
+        .decrypt(Nonce::from_slice(&header[1 + KEY_ID_LEN..]), Payload {
+            msg: ciphertext,
+            aad: &aad(header, id),
+        })
+        .map_err(|_| MegadexDbError::CryptoError("Decryption failed, the record may have been tampered with".into()))
+}
+
+/// The id of the key that a blob produced by `seal` was encrypted with
+pub fn key_id(blob: &[u8]) -> Result<u32, MegadexDbError> {
+    if blob.len() < HEADER_LEN || blob[0] != ENVELOPE_V1 {
+        return Err(MegadexDbError::CryptoError("Record is not encrypted".into()));
+    }
+    let mut id = [0u8; KEY_ID_LEN];
+    id.copy_from_slice(&blob[1..1 + KEY_ID_LEN]);
+    Ok(u32::from_be_bytes(id))
+}
@@ -0,0 +1,149 @@
+use crate::backend::Backend;
+use crate::codec::Codec;
+use crate::error::MegadexDbError;
+use crate::expiry;
+use crate::import::{
+    Conflict,
+    ImportStats,
+    RowError,
+    IMPORT_BATCH,
+};
+use crate::record::Record;
+use crate::MegadexDb;
+use serde::{
+    de::{
+        self,
+        DeserializeOwned,
+        Visitor,
+    },
+    forward_to_deserialize_any,
+    Deserializer,
+    Serialize,
+};
+use std::io::{
+    Read,
+    Write,
+};
+
+/// The outcome of an `import_csv`
+#[derive(Debug, Default, PartialEq)]
+pub struct CsvImport {
+    pub stats: ImportStats,
+    /// The rows which were left out, in order
+    pub errors: Vec<RowError>,
+}
+
+fn csv_err(e: csv::Error) -> MegadexDbError {
+    MegadexDbError::CodecError(format!("{}", e))
+}
+
+fn line_of(pos: Option<&csv::Position>, fallback: usize) -> usize {
+    pos.map_or(fallback, |p| p.line() as usize)
+}
+
+/// The column names serde expects for `T`, found by starting to deserialize one.
+/// Renamed and skipped fields are accounted for, a flattened struct has none.
+fn serde_columns<T: DeserializeOwned>() -> Vec<&'static str> {
+    let mut columns = Vec::new();
+    let _ = T::deserialize(FieldNames(&mut columns));
+    columns
+}
+
+/// A deserializer which only records the field names of a struct
+struct FieldNames<'c>(&'c mut Vec<&'static str>);
+
+impl<'de, 'c> Deserializer<'de> for FieldNames<'c> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("only the field names of a struct are read"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.0.extend_from_slice(fields);
+        self.deserialize_any(visitor)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option unit
+        unit_struct newtype_struct seq tuple tuple_struct map enum identifier ignored_any
+    }
+}
+
+impl<T, C, B> MegadexDb<T, C, B>
+where
+    T: Record + Serialize + DeserializeOwned,
+    C: Codec,
+    B: Backend,
+{
+    /// Write every record of the collection to `writer` as a row of CSV, under a header row of
+    /// the field names as serde sees them. Only flat structs can be written this way, the CSV serializer
+    /// rejects nested structs, sequences and maps. Returns the number of records written.
+    pub fn export_csv<W: Write>(&self, writer: W) -> Result<usize, MegadexDbError> {
+        // the header is written along with the first record, from the names it is serialized with
+        let mut out = csv::Writer::from_writer(writer);
+        let mut count = 0;
+        self.db.read(|txn| {
+            let now = expiry::now();
+            txn.scan(self.main, None, &mut |id, blob| {
+                if self.hidden(txn, id, now)? {
+                    return Ok(true);
+                }
+                out.serialize(self.decode_value(id, blob)?).map_err(csv_err)?;
+                count += 1;
+                Ok(true)
+            })
+        })?;
+        if count == 0 {
+            out.write_record(serde_columns::<T>()).map_err(csv_err)?;
+        }
+        out.flush()?;
+        Ok(count)
+    }
+
+    /// Read rows with a header of field names, as written by `export_csv`, and store them along
+    /// with their index entries. The columns may come in any order.
+    ///
+    /// Rows that can't be parsed, or are rejected by `Conflict::Fail`, are reported in
+    /// `CsvImport::errors` and the import carries on. The rows are written in batches of
+    /// one transaction each.
+    pub fn import_csv<R: Read>(&self, reader: R, conflict: Conflict) -> Result<CsvImport, MegadexDbError> {
+        let mut input = csv::Reader::from_reader(reader);
+        let headers = input.headers().map_err(csv_err)?.clone();
+        let mut report = CsvImport::default();
+        let mut batch = Vec::with_capacity(IMPORT_BATCH);
+        for (n, row) in input.records().enumerate() {
+            // the header is on line 1
+            let fallback = n + 2;
+            match row {
+                Ok(row) => {
+                    let line = line_of(row.position(), fallback);
+                    match row.deserialize(Some(&headers)) {
+                        Ok(obj) => batch.push((line, obj)),
+                        Err(e) => report.errors.push(RowError {
+                            line,
+                            error: csv_err(e),
+                        }),
+                    }
+                },
+                Err(e) if e.is_io_error() => return Err(csv_err(e)),
+                Err(e) => report.errors.push(RowError {
+                    line: line_of(e.position(), fallback),
+                    error: csv_err(e),
+                }),
+            }
+            if batch.len() == IMPORT_BATCH {
+                self.import_batch(&batch, conflict, &mut report.stats, Some(&mut report.errors))?;
+                batch.clear();
+            }
+        }
+        self.import_batch(&batch, conflict, &mut report.stats, Some(&mut report.errors))?;
+        report.errors.sort_by_key(|e| e.line);
+        Ok(report)
+    }
+}
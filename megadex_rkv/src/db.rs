@@ -0,0 +1,745 @@
+use crate::backend::{
+    Backend,
+    ReadTxn,
+    Visitor,
+    WriteTxn,
+};
+use crate::error::MegadexDbError;
+use crate::stats::PageStats;
+use lazy_static::lazy_static;
+use lmdb::{
+    Cursor,
+    DatabaseFlags,
+    EnvironmentFlags,
+};
+use rkv::{
+    MultiStore,
+    Readable,
+    Reader,
+    Rkv,
+    SingleStore,
+    StoreError,
+    StoreOptions,
+    Value,
+    Writer,
+};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::mem;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::str;
+use std::sync::{
+    Arc,
+    Mutex,
+    MutexGuard,
+    RwLock,
+    Weak,
+};
+use tempfile::{
+    Builder,
+    TempDir,
+};
+
+/// The number of named stores rkv allows by default
+const DEFAULT_MAX_DBS: u32 = 5;
+
+lazy_static! {
+    /// The environments which are open in this process. LMDB must not open the same
+    /// environment twice in one process, so `Db`s opened on the same path share it.
+    /// Unlike rkv's `Manager` only weak references are kept, so an environment is closed
+    /// once the last `Db` (and `MegadexDb`) using it is dropped, and can then be reopened
+    /// with different options.
+    static ref ENVS: Mutex<HashMap<PathBuf, OpenEnv>> = Mutex::new(HashMap::new());
+}
+
+struct OpenEnv {
+    env: Weak<RwLock<Rkv>>,
+    options: DbBuilder,
+    resizes: Arc<Mutex<ResizeStats>>,
+    opened: Arc<Mutex<HashMap<String, RkvStore>>>,
+}
+
+/// A store which was opened in this process, see `Db::stores`
+#[derive(Clone, Copy)]
+enum RkvStore {
+    Single(SingleStore),
+    Multi(MultiStore),
+}
+
+/// The kind of a named store
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StoreKind {
+    /// One value per key, like the main store of a collection
+    Single,
+    /// Sorted duplicate values per key, like an index
+    Multi,
+}
+
+/// A named store of a `Db`, see `Db::stores`
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoreInfo {
+    pub name: String,
+    pub kind: StoreKind,
+    /// The number of values, as of the last commit
+    pub entries: usize,
+}
+
+/// A specialized database environment that is persisted to the provided directory.
+#[derive(Clone)]
+pub struct Db {
+    pub(crate) env: Arc<RwLock<Rkv>>,
+    path: PathBuf,
+    /// Keeps the directory of a temporary `Db` alive until the last handle is dropped
+    temp: Option<Arc<TempDir>>,
+    read_only: bool,
+    growth: Option<MapGrowth>,
+    resizes: Arc<Mutex<ResizeStats>>,
+    /// The stores of the environment which were opened in this process, by name
+    opened: Arc<Mutex<HashMap<String, RkvStore>>>,
+}
+
+/// How a `Db` grows its memory map when a write transaction runs out of space
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct MapGrowth {
+    factor: f64,
+    max_size: usize,
+}
+
+/// Counters describing the automatic map growth of a `Db`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ResizeStats {
+    /// The number of times the map was grown
+    pub resizes: u64,
+    /// The number of writes which failed because the map had already reached `MapGrowth::max_size`
+    pub exhausted: u64,
+    /// The map size after the last resize
+    pub map_size: usize,
+}
+
+impl Db {
+    /// Construct a new collection of indexes in a temp directory
+    /// This will create The main struct store for T and
+    /// the supporting secondary indexes to find the id for T
+    /// The directory is deleted when the last handle to the `Db` is dropped.
+    pub fn new_temp() -> Result<Db, MegadexDbError> {
+        DbBuilder::new().open_temp()
+    }
+
+    /// Construct a new collection of indexes in the supplied directory,
+    /// or if one already exists, it will use it.
+    /// This will create The main struct store for T and
+    /// the supporting secondary indexes to find the id for T
+    pub fn new<'p, P: Into<&'p Path>>(dir: P) -> Result<Self, MegadexDbError> {
+        DbBuilder::new().open(dir.into())
+    }
+
+    /// Open an existing database read-only, see `ReadOnlyMegadexDb`
+    pub fn open_read_only<P: AsRef<Path>>(dir: P) -> Result<Db, MegadexDbError> {
+        DbBuilder::new().read_only(true).open(dir)
+    }
+
+    /// Close this handle. If it was the last handle to the environment the environment
+    /// is closed as well, otherwise this fails with `EnvInUse` (the handle is dropped regardless).
+    pub fn close(self) -> Result<(), MegadexDbError> {
+        let mut envs = ENVS.lock()?;
+        if Arc::strong_count(&self.env) > 1 {
+            return Err(MegadexDbError::EnvInUse(self.path.display().to_string()));
+        }
+        envs.remove(&fs::canonicalize(&self.path).unwrap_or_else(|_| self.path.clone()));
+        Ok(())
+    }
+
+    /// Delete the database files in `dir`, and `dir` itself if nothing else is left in it.
+    /// Fails with `EnvInUse` if the environment is open in this process.
+    pub fn destroy<P: AsRef<Path>>(dir: P) -> Result<(), MegadexDbError> {
+        let dir = dir.as_ref();
+        let _envs = ensure_closed(dir)?;
+        for file in ["data.mdb", "lock.mdb"].iter() {
+            let path = dir.join(file);
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+        if fs::read_dir(dir)?.next().is_none() {
+            fs::remove_dir(dir)?;
+        }
+        Ok(())
+    }
+
+    /// The directory the environment is stored in
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Copy the database into the directory `dest`, which is created if needed
+    /// and must not already hold a database.
+    ///
+    /// The copy is made from a single read transaction with `mdb_env_copy2`, so writers carry on meanwhile.
+    /// With `compact` the free pages are left behind and the pages are renumbered, which takes longer
+    /// but gives a smaller file.
+    pub fn backup_to<P: AsRef<Path>>(&self, dest: P, compact: bool) -> Result<(), MegadexDbError> {
+        let dest = dest.as_ref();
+        fs::create_dir_all(dest)?;
+        if dest.join("data.mdb").exists() {
+            return Err(MegadexDbError::ValueError(format!("{} already holds a database", dest.display())));
+        }
+        let path = dest
+            .to_str()
+            .and_then(|path| CString::new(path).ok())
+            .ok_or_else(|| MegadexDbError::ValueError(format!("{} is not a valid LMDB path", dest.display())))?;
+        let flags = if compact {
+            lmdb_sys::MDB_CP_COMPACT
+        } else {
+            0
+        };
+        // LMDB allows one read transaction per thread, so the copy starts its own once `raw_read` is done
+        let env = self.raw_read(None, |_, cursor, _| Ok(unsafe { lmdb_sys::mdb_txn_env(cursor_txn(cursor)) }))?;
+        let _envlock = self.env.read()?;
+        match unsafe { lmdb_sys::mdb_env_copy2(env, path.as_ptr(), flags) } {
+            lmdb_sys::MDB_SUCCESS => Ok(()),
+            code => Err(lmdb::Error::from_err_code(code).into()),
+        }
+    }
+
+    /// Replace the database in `dir` with a copy made by `backup_to`.
+    /// The environment in `dir` must not be open in this process.
+    pub fn restore_from<P: AsRef<Path>, Q: AsRef<Path>>(backup: P, dir: Q) -> Result<(), MegadexDbError> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let _envs = ensure_closed(dir)?;
+        let restored = dir.join("data.mdb.restore");
+        fs::copy(backup.as_ref().join("data.mdb"), &restored)?;
+        fs::rename(restored, dir.join("data.mdb"))?;
+        // the reader table of the replaced database is stale
+        let lock = dir.join("lock.mdb");
+        if lock.exists() {
+            fs::remove_file(lock)?;
+        }
+        Ok(())
+    }
+
+    /// The named stores in this environment, their kinds and sizes. LMDB keeps an entry for every named store
+    /// in its unnamed database, so this also lists the collections which haven't been opened in this process.
+    /// No stores are opened to list them: the stores which are open already are read with `mdb_dbi_flags` and
+    /// `mdb_stat`, and the others from the record LMDB keeps in their entry.
+    pub fn stores(&self) -> Result<Vec<StoreInfo>, MegadexDbError> {
+        let opened = self.opened.lock()?.clone();
+        self.raw_read(None, |reader, cursor, _| {
+            let txn = cursor_txn(cursor);
+            let mut stores = Vec::new();
+            for item in cursor.iter_start() {
+                let (key, record) = item?;
+                let name = match str::from_utf8(key) {
+                    Ok(name) => name,
+                    Err(_) => continue,
+                };
+                let (flags, entries) = match opened.get(name) {
+                    Some(store) => {
+                        let db = handle(reader, *store)?;
+                        (dbi_flags(txn, db)?, dbi_stat(txn, db)?.ms_entries)
+                    },
+                    None => match store_record(record) {
+                        Some(record) => record,
+                        None => continue,
+                    },
+                };
+                let kind = if flags & DatabaseFlags::DUP_SORT.bits() != 0 {
+                    StoreKind::Multi
+                } else {
+                    StoreKind::Single
+                };
+                stores.push(StoreInfo {
+                    name: name.to_string(),
+                    kind,
+                    entries,
+                });
+            }
+            Ok(stores)
+        })
+    }
+
+    /// Configure the LMDB environment before opening it
+    pub fn builder() -> DbBuilder {
+        DbBuilder::new()
+    }
+
+    /// The LMDB info and page statistics of the environment
+    pub(crate) fn env_stats(&self) -> Result<(lmdb::Info, lmdb::Stat), MegadexDbError> {
+        let envlock = self.env.read()?;
+        Ok((envlock.info()?, envlock.stat()?))
+    }
+
+    /// The page statistics of the named store, which `mdb_stat` reads from its own B-tree
+    pub(crate) fn store_stat(&self, name: &str) -> Result<PageStats, MegadexDbError> {
+        self.raw_read(Some(name), |_, cursor, db| Ok(dbi_stat(cursor_txn(cursor), db)?.into()))
+    }
+
+    /// Run `f` on a cursor over the store `name`, or the unnamed database, in a new read transaction.
+    /// rkv keeps the LMDB handles of its stores to itself, but passes them to the `Readable` it reads
+    /// through, and the cursor leads on to the transaction and the environment for the `mdb_*` calls
+    /// rkv doesn't wrap.
+    fn raw_read<R, F>(&self, name: Option<&str>, f: F) -> Result<R, MegadexDbError>
+    where
+        F: FnOnce(&Reader, &mut lmdb::RoCursor, lmdb::Database) -> Result<R, MegadexDbError>,
+    {
+        let store = {
+            let envlock = self.env.write()?;
+            envlock.open_single(name, StoreOptions::default()).map_err(|e| not_found(name.unwrap_or_default(), e))?
+        };
+        let envlock = self.env.read()?;
+        let reader = envlock.read()?;
+        let db = handle(&reader, RkvStore::Single(store))?;
+        let mut cursor = reader.open_ro_cursor(db)?;
+        f(&reader, &mut cursor, db)
+    }
+
+    /// The automatic map growth of the environment so far, across every `Db` opened on its path
+    pub fn resize_stats(&self) -> ResizeStats {
+        *self.resizes.lock().expect("Failed to acquire resize stats lock")
+    }
+
+    fn try_write<R, F>(&self, f: &mut F) -> Result<R, MegadexDbError>
+    where
+        F: FnMut(&mut dyn WriteTxn<Self>) -> Result<R, MegadexDbError>,
+    {
+        let envlock = self.env.read().expect("Failed to acquire read lock");
+        let mut txn = RkvTxn(envlock.write()?);
+        let res = f(&mut txn)?;
+        txn.0.commit()?;
+        Ok(res)
+    }
+
+    /// Grow the map after a write ran out of space, returning whether the write should be retried.
+    /// LMDB only allows resizing while no transactions are open, so this waits on the env write lock
+    /// for the readers and writers of this process to finish.
+    fn grow(&self) -> Result<bool, MegadexDbError> {
+        let growth = match self.growth {
+            Some(growth) => growth,
+            None => return Ok(false),
+        };
+        let envlock = self.env.write()?;
+        let current = envlock.info()?.map_size();
+        let page_size = envlock.stat()?.page_size() as usize;
+        let mut size = ((current as f64 * growth.factor) as usize).min(growth.max_size);
+        size -= size % page_size;
+
+        let mut stats = self.resizes.lock()?;
+        if size <= current {
+            stats.exhausted += 1;
+            return Ok(false);
+        }
+        envlock.set_map_size(size)?;
+        stats.resizes += 1;
+        stats.map_size = size;
+        Ok(true)
+    }
+
+    /// A read-only environment can only open stores which already exist
+    fn store_options(&self, create: bool) -> StoreOptions {
+        if create && !self.read_only {
+            StoreOptions::create()
+        } else {
+            StoreOptions::default()
+        }
+    }
+}
+
+/// Opens a `Db` with non-default LMDB settings.
+///
+/// Each `#[indexed]` field takes up a named store in addition to the main store, and the bookkeeping
+/// of the collection one more, so collections with many indexes (or several collections in one `Db`)
+/// need a larger `max_dbs`.
+///
+/// Environments are shared per path within a process, so every `Db` opened on a path
+/// while it is open must use the same settings.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DbBuilder {
+    map_size: Option<usize>,
+    max_dbs: u32,
+    max_readers: Option<u32>,
+    flags: EnvironmentFlags,
+    mode: Option<u32>,
+    growth: Option<MapGrowth>,
+}
+
+impl Default for DbBuilder {
+    fn default() -> DbBuilder {
+        DbBuilder {
+            map_size: None,
+            max_dbs: DEFAULT_MAX_DBS,
+            max_readers: None,
+            flags: EnvironmentFlags::empty(),
+            mode: None,
+            growth: None,
+        }
+    }
+}
+
+impl DbBuilder {
+    pub fn new() -> DbBuilder {
+        DbBuilder::default()
+    }
+
+    /// The maximum size of the database in bytes, LMDB defaults to 10MB.
+    /// It should be a multiple of the OS page size.
+    pub fn map_size(mut self, bytes: usize) -> Self {
+        self.map_size = Some(bytes);
+        self
+    }
+
+    /// The maximum number of named stores, including the main store of each collection
+    pub fn max_dbs(mut self, max_dbs: u32) -> Self {
+        self.max_dbs = max_dbs;
+        self
+    }
+
+    /// The maximum number of concurrent read transactions, LMDB defaults to 126
+    pub fn max_readers(mut self, max_readers: u32) -> Self {
+        self.max_readers = Some(max_readers);
+        self
+    }
+
+    /// Don't fsync after a commit (`MDB_NOSYNC`). A system crash may undo the last transactions.
+    pub fn no_sync(self, on: bool) -> Self {
+        self.flag(EnvironmentFlags::NO_SYNC, on)
+    }
+
+    /// Don't fsync the meta page after a commit (`MDB_NOMETASYNC`).
+    /// A system crash may undo the last transaction.
+    pub fn no_meta_sync(self, on: bool) -> Self {
+        self.flag(EnvironmentFlags::NO_META_SYNC, on)
+    }
+
+    /// Use a writeable memory map (`MDB_WRITEMAP`). Faster writes, but stray pointer
+    /// writes in the process can corrupt the database.
+    pub fn write_map(self, on: bool) -> Self {
+        self.flag(EnvironmentFlags::WRITE_MAP, on)
+    }
+
+    /// Open the environment read-only (`MDB_RDONLY`). Stores are not created,
+    /// so every collection must already exist.
+    pub fn read_only(self, on: bool) -> Self {
+        self.flag(EnvironmentFlags::READ_ONLY, on)
+    }
+
+    /// The unix permissions of the database files, e.g. `0o600`
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Grow the map by `factor` whenever a write transaction fails with `MDB_MAP_FULL`, up to
+    /// `max_size` bytes, and retry the transaction. The resizes are counted in `Db::resize_stats`.
+    pub fn map_growth(mut self, factor: f64, max_size: usize) -> Self {
+        assert!(factor > 1.0, "The map growth factor must be greater than 1");
+        self.growth = Some(MapGrowth {
+            factor,
+            max_size,
+        });
+        self
+    }
+
+    fn flag(mut self, flag: EnvironmentFlags, on: bool) -> Self {
+        if on {
+            self.flags.insert(flag);
+        } else {
+            self.flags.remove(flag);
+        }
+        self
+    }
+
+    /// Open (or create) the environment in `dir`, which must exist.
+    /// If the environment is already open in this process it is shared, which fails with `EnvOptionsDiffer`
+    /// unless it was opened with the same options.
+    pub fn open<P: AsRef<Path>>(&self, dir: P) -> Result<Db, MegadexDbError> {
+        let dir = dir.as_ref();
+        let key = fs::canonicalize(dir)?;
+        let mut envs = ENVS.lock()?;
+        envs.retain(|_, open| open.env.strong_count() > 0);
+        let shared = envs.get(&key).and_then(|open| Some((open.env.upgrade()?, open)));
+        let (env, resizes, opened) = match shared {
+            Some((_, open)) if open.options != *self => {
+                return Err(MegadexDbError::EnvOptionsDiffer(dir.display().to_string()));
+            },
+            Some((env, open)) => (env, open.resizes.clone(), open.opened.clone()),
+            None => {
+                if let (Some(mode), false) = (self.mode, self.flags.contains(EnvironmentFlags::READ_ONLY)) {
+                    create_files(&key, mode)?;
+                }
+                let mut builder = Rkv::environment_builder();
+                builder.set_max_dbs(self.max_dbs).set_flags(self.flags);
+                if let Some(map_size) = self.map_size {
+                    builder.set_map_size(map_size);
+                }
+                if let Some(max_readers) = self.max_readers {
+                    builder.set_max_readers(max_readers);
+                }
+                let env = Arc::new(RwLock::new(Rkv::from_env(key.as_path(), builder)?));
+                let resizes = Arc::new(Mutex::new(ResizeStats::default()));
+                let opened = Arc::new(Mutex::new(HashMap::new()));
+                envs.insert(key, OpenEnv {
+                    env: Arc::downgrade(&env),
+                    options: self.clone(),
+                    resizes: resizes.clone(),
+                    opened: opened.clone(),
+                });
+                (env, resizes, opened)
+            },
+        };
+        Ok(Db {
+            env,
+            path: dir.to_path_buf(),
+            temp: None,
+            read_only: self.flags.contains(EnvironmentFlags::READ_ONLY),
+            growth: self.growth,
+            resizes,
+            opened,
+        })
+    }
+
+    /// Open the environment in a new temp directory, which is deleted when
+    /// the last handle to the `Db` is dropped
+    pub fn open_temp(&self) -> Result<Db, MegadexDbError> {
+        let root = Builder::new().prefix("megadex").tempdir()?;
+        let mut db = self.open(root.path())?;
+        db.temp = Some(Arc::new(root));
+        Ok(db)
+    }
+}
+
+/// rkv opens the files of a new environment with the default permissions. LMDB keeps the permissions
+/// of files which exist, and takes an empty data file for a new environment, so they are created with
+/// `mode` beforehand.
+fn create_files(dir: &Path, mode: u32) -> Result<(), MegadexDbError> {
+    for file in ["data.mdb", "lock.mdb"].iter() {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(mode);
+        match options.open(dir.join(file)) {
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {},
+            res => {
+                res?;
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Fail with `EnvInUse` if the environment in `dir` is open in this process.
+/// The registry stays locked until the returned guard is dropped, so it can't be opened in the meantime.
+fn ensure_closed(dir: &Path) -> Result<MutexGuard<'static, HashMap<PathBuf, OpenEnv>>, MegadexDbError> {
+    let envs = ENVS.lock()?;
+    if envs.get(&fs::canonicalize(dir)?).and_then(|open| open.env.upgrade()).is_some() {
+        return Err(MegadexDbError::EnvInUse(dir.display().to_string()));
+    }
+    Ok(envs)
+}
+
+impl Backend for Db {
+    type Single = SingleStore;
+    type Multi = MultiStore;
+
+    fn open_single(&self, name: &str, create: bool) -> Result<SingleStore, MegadexDbError> {
+        let envlock = self.env.write().expect("failed to acquire env write lock");
+        let store = envlock.open_single(name, self.store_options(create)).map_err(|e| not_found(name, e))?;
+        self.opened.lock()?.insert(name.into(), RkvStore::Single(store));
+        Ok(store)
+    }
+
+    fn open_multi(&self, name: &str, create: bool) -> Result<MultiStore, MegadexDbError> {
+        let envlock = self.env.write().expect("failed to acquire env write lock");
+        let store = envlock.open_multi(name, self.store_options(create)).map_err(|e| not_found(name, e))?;
+        self.opened.lock()?.insert(name.into(), RkvStore::Multi(store));
+        Ok(store)
+    }
+
+    fn read<R, F>(&self, f: F) -> Result<R, MegadexDbError>
+    where
+        F: FnOnce(&dyn ReadTxn<Self>) -> Result<R, MegadexDbError>,
+    {
+        let envlock = self.env.read().expect("Failed to acquire read lock");
+        let txn = RkvTxn(envlock.read()?);
+        f(&txn)
+    }
+
+    fn write<R, F>(&self, mut f: F) -> Result<R, MegadexDbError>
+    where
+        F: FnMut(&mut dyn WriteTxn<Self>) -> Result<R, MegadexDbError>,
+    {
+        loop {
+            match self.try_write(&mut f) {
+                Err(MegadexDbError::RkvError(StoreError::LmdbError(lmdb::Error::MapFull))) if self.grow()? => continue,
+                res => return res,
+            }
+        }
+    }
+
+    fn page_stats(&self, name: &str) -> Result<Option<PageStats>, MegadexDbError> {
+        self.store_stat(name).map(Some)
+    }
+}
+
+/// An rkv `Reader` or `Writer`
+struct RkvTxn<T>(T);
+
+/// A `Readable` which notes the LMDB handle of the store read through it, see `Db::raw_read`
+struct Probe<'r, R> {
+    reader: &'r R,
+    db: Cell<Option<lmdb::Database>>,
+}
+
+impl<R: Readable> Readable for Probe<'_, R> {
+    fn get<K: AsRef<[u8]>>(&self, db: lmdb::Database, k: &K) -> Result<Option<Value<'_>>, StoreError> {
+        self.db.set(Some(db));
+        self.reader.get(db, k)
+    }
+
+    fn open_ro_cursor(&self, db: lmdb::Database) -> Result<lmdb::RoCursor<'_>, StoreError> {
+        self.db.set(Some(db));
+        self.reader.open_ro_cursor(db)
+    }
+}
+
+/// The LMDB handle of an rkv store
+fn handle<R: Readable>(reader: &R, store: RkvStore) -> Result<lmdb::Database, MegadexDbError> {
+    let probe = Probe {
+        reader,
+        db: Cell::new(None),
+    };
+    // only the handle matters, not what is found
+    let _ = match store {
+        RkvStore::Single(store) => store.get(&probe, [0u8]).map(drop),
+        RkvStore::Multi(store) => store.get(&probe, [0u8]).map(drop),
+    };
+    probe.db.get().ok_or_else(|| MegadexDbError::ValueError("rkv read without a store handle".into()))
+}
+
+/// The raw transaction of a cursor
+fn cursor_txn(cursor: &lmdb::RoCursor) -> *mut lmdb_sys::MDB_txn {
+    unsafe { lmdb_sys::mdb_cursor_txn(cursor.cursor()) }
+}
+
+fn dbi_flags(txn: *mut lmdb_sys::MDB_txn, db: lmdb::Database) -> Result<u32, MegadexDbError> {
+    let mut flags = 0;
+    match unsafe { lmdb_sys::mdb_dbi_flags(txn, db.dbi(), &mut flags) } {
+        lmdb_sys::MDB_SUCCESS => Ok(flags),
+        code => Err(lmdb::Error::from_err_code(code).into()),
+    }
+}
+
+fn dbi_stat(txn: *mut lmdb_sys::MDB_txn, db: lmdb::Database) -> Result<lmdb_sys::MDB_stat, MegadexDbError> {
+    let mut stat: lmdb_sys::MDB_stat = unsafe { mem::zeroed() };
+    match unsafe { lmdb_sys::mdb_stat(txn, db.dbi(), &mut stat) } {
+        lmdb_sys::MDB_SUCCESS => Ok(stat),
+        code => Err(lmdb::Error::from_err_code(code).into()),
+    }
+}
+
+/// The flags and entries of a named store from its entry in the unnamed database, where LMDB keeps the
+/// `MDB_db` record which `mdb_dbi_flags` and `mdb_stat` read once the store is open: a u32 of padding,
+/// the u16 flags and the u16 depth, then the branch, leaf and overflow pages, the entries and the root
+/// page, each a `size_t`. `None` if the entry isn't a store.
+fn store_record(record: &[u8]) -> Option<(u32, usize)> {
+    const WORD: usize = mem::size_of::<usize>();
+    if record.len() != 8 + 5 * WORD {
+        return None;
+    }
+    let mut entries = [0; WORD];
+    entries.copy_from_slice(&record[8 + 3 * WORD..8 + 4 * WORD]);
+    Some((u16::from_ne_bytes([record[4], record[5]]).into(), usize::from_ne_bytes(entries)))
+}
+
+fn blob(value: Option<Value<'_>>) -> Result<Option<&[u8]>, MegadexDbError> {
+    match value {
+        Some(Value::Blob(bytes)) => Ok(Some(bytes)),
+        Some(v) => Err(MegadexDbError::InvalidType("Blob".into(), format!("{:?}", v))),
+        None => Ok(None),
+    }
+}
+
+fn not_found(name: &str, err: StoreError) -> MegadexDbError {
+    match err {
+        StoreError::LmdbError(lmdb::Error::NotFound) => MegadexDbError::StoreNotFound(name.into()),
+        e => e.into(),
+    }
+}
+
+/// rkv reports deleting a missing key as an error
+fn found(res: Result<(), StoreError>) -> Result<bool, MegadexDbError> {
+    match res {
+        Ok(()) => Ok(true),
+        Err(StoreError::LmdbError(lmdb::Error::NotFound)) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+impl<T: Readable> ReadTxn<Db> for RkvTxn<T> {
+    fn get(&self, store: SingleStore, key: &[u8]) -> Result<Option<Vec<u8>>, MegadexDbError> {
+        Ok(blob(store.get(&self.0, key)?)?.map(|b| b.to_vec()))
+    }
+
+    fn get_dup(&self, store: MultiStore, key: &[u8]) -> Result<Vec<Vec<u8>>, MegadexDbError> {
+        let mut values = Vec::new();
+        for item in store.get(&self.0, key)? {
+            if let Some(b) = blob(item?.1)? {
+                values.push(b.to_vec());
+            }
+        }
+        Ok(values)
+    }
+
+    fn scan(&self, store: SingleStore, from: Option<&[u8]>, f: &mut Visitor) -> Result<(), MegadexDbError> {
+        let iter = match from {
+            Some(from) => store.iter_from(&self.0, from)?,
+            None => store.iter_start(&self.0)?,
+        };
+        for item in iter {
+            let (key, value) = item?;
+            if let Some(b) = blob(value)? {
+                if !f(key, b)? {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn scan_dup(&self, store: MultiStore, f: &mut Visitor) -> Result<(), MegadexDbError> {
+        for item in store.iter_start(&self.0)? {
+            let (key, value) = item?;
+            if let Some(b) = blob(value)? {
+                if !f(key, b)? {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'e> WriteTxn<Db> for RkvTxn<Writer<'e>> {
+    fn put(&mut self, store: SingleStore, key: &[u8], value: &[u8]) -> Result<(), MegadexDbError> {
+        store.put(&mut self.0, key, &Value::Blob(value)).map_err(|e| e.into())
+    }
+
+    fn delete(&mut self, store: SingleStore, key: &[u8]) -> Result<bool, MegadexDbError> {
+        found(store.delete(&mut self.0, key))
+    }
+
+    fn put_dup(&mut self, store: MultiStore, key: &[u8], value: &[u8]) -> Result<(), MegadexDbError> {
+        store.put(&mut self.0, key, &Value::Blob(value)).map_err(|e| e.into())
+    }
+
+    fn delete_dup(&mut self, store: MultiStore, key: &[u8], value: &[u8]) -> Result<bool, MegadexDbError> {
+        found(store.delete(&mut self.0, key, &Value::Blob(value)))
+    }
+}
@@ -0,0 +1,137 @@
+use crate::backend::Backend;
+use crate::compress::Compression;
+use crate::error::MegadexDbError;
+use crate::history;
+use crate::internal::{
+    self,
+    Section,
+};
+
+/// The high bits of the header byte in front of a blob in the main store.
+/// The low bits are the flags below.
+const MARKER: u8 = 0xf0;
+const MARKER_MASK: u8 = 0xf8;
+
+/// The header is followed by the version of the record, see `MegadexDb::with_versions`
+pub(crate) const VERSIONED: u8 = 1;
+/// The body is compressed, see `Compression`
+pub(crate) const COMPRESSED: u8 = 2;
+/// The body is encrypted, after it was compressed
+pub(crate) const ENCRYPTED: u8 = 4;
+
+const VERSION_LEN: usize = 8;
+
+/// The key of the `FORMAT` section which says that every blob has the header
+const FRAMED: &[u8] = b"framed";
+
+/// A blob of the main store, taken apart
+pub(crate) struct Envelope<'b> {
+    pub flags: u8,
+    pub version: Option<u64>,
+    pub body: &'b [u8],
+}
+
+impl<'b> Envelope<'b> {
+    /// A blob without the header, as written by older versions of this crate
+    pub fn plain(blob: &'b [u8]) -> Envelope<'b> {
+        Envelope {
+            flags: 0,
+            version: None,
+            body: blob,
+        }
+    }
+
+    pub fn parse(blob: &'b [u8]) -> Result<Envelope<'b>, MegadexDbError> {
+        let (flags, rest) = match blob.split_first() {
+            Some((header, rest)) if header & MARKER_MASK == MARKER => (header & !MARKER_MASK, rest),
+            _ => return Err(MegadexDbError::ValueError("Missing record header".into())),
+        };
+        if flags & VERSIONED == 0 {
+            return Ok(Envelope {
+                flags,
+                version: None,
+                body: rest,
+            });
+        }
+        if rest.len() < VERSION_LEN {
+            return Err(MegadexDbError::ValueError("Missing version header".into()));
+        }
+        let (version, body) = rest.split_at(VERSION_LEN);
+        let mut buf = [0; VERSION_LEN];
+        buf.copy_from_slice(version);
+        Ok(Envelope {
+            flags,
+            version: Some(u64::from_be_bytes(buf)),
+            body,
+        })
+    }
+}
+
+/// Put the header, and the version if there is one, in front of a compressed and/or encrypted body
+/// described by `flags`
+pub(crate) fn frame(flags: u8, version: Option<u64>, body: &[u8]) -> Vec<u8> {
+    let mut blob = Vec::with_capacity(1 + VERSION_LEN + body.len());
+    match version {
+        Some(version) => {
+            blob.push(MARKER | flags | VERSIONED);
+            blob.extend_from_slice(&version.to_be_bytes());
+        },
+        None => blob.push(MARKER | flags),
+    }
+    blob.extend_from_slice(body);
+    blob
+}
+
+/// Whether the blobs of the collection in `db` have the envelope header, see `plain_value`.
+/// Collections written by older versions of this crate have none until they are opened with `MegadexDb::new`.
+pub fn is_framed<B: Backend>(db: &B) -> Result<bool, MegadexDbError> {
+    let format = match Section::open(db, internal::FORMAT, false) {
+        Ok(format) => format,
+        Err(MegadexDbError::StoreNotFound(_)) => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    db.read(|txn| Ok(format.get(txn, FRAMED)?.is_some()))
+}
+
+/// Put the header in front of the blobs of the main store and the history which were written without one,
+/// and note that they all have it. There is no telling a blob without the header from one with it by
+/// looking, as the encoded value may start with any byte.
+pub(crate) fn frame_collection<B: Backend>(db: &B, main: B::Single) -> Result<(), MegadexDbError> {
+    let format = Section::open(db, internal::FORMAT, true)?;
+    if db.read(|txn| format.get(txn, FRAMED))?.is_some() {
+        return Ok(());
+    }
+    db.write(|txn| {
+        if format.get(&*txn, FRAMED)?.is_some() {
+            return Ok(());
+        }
+        let mut plain = Vec::new();
+        txn.scan(main, None, &mut |id, blob| {
+            plain.push((id.to_vec(), frame(0, None, blob)));
+            Ok(true)
+        })?;
+        for (id, blob) in plain.iter() {
+            txn.put(main, id, blob)?;
+        }
+        history::frame_history_txn(txn, format.other(internal::HISTORY))?;
+        format.put(txn, FRAMED, &[1])
+    })
+}
+
+/// The encoded value in a blob of the main store of a collection, without the envelope header if the
+/// collection is `framed`, and decompressed. This is for tools which read a database without knowing how
+/// its collections are set up, so it fails for encrypted records and records compressed with a dictionary.
+pub fn plain_value(blob: &[u8], framed: bool) -> Result<Vec<u8>, MegadexDbError> {
+    let envelope = if framed {
+        Envelope::parse(blob)?
+    } else {
+        Envelope::plain(blob)
+    };
+    if envelope.flags & ENCRYPTED != 0 {
+        return Err(MegadexDbError::CryptoError("Record is encrypted".into()));
+    }
+    if envelope.flags & COMPRESSED != 0 {
+        return Compression::Uncompressed.decompress(envelope.body);
+    }
+    Ok(envelope.body.to_vec())
+}
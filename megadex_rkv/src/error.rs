@@ -20,6 +20,24 @@ pub enum MegadexDbError {
     InvalidType(String, String),
     #[fail(display = "Value error : {}", 0)]
     ValueError(String),
+    #[fail(display = "Codec error : {}", 0)]
+    CodecError(String),
+    #[fail(display = "Compression error : {}", 0)]
+    CompressionError(String),
+    #[fail(display = "Crypto error : {}", 0)]
+    CryptoError(String),
+    #[fail(display = "Environment {} is still in use", 0)]
+    EnvInUse(String),
+    #[fail(display = "Environment {} is already open with other options", 0)]
+    EnvOptionsDiffer(String),
+    #[fail(display = "Store {} does not exist", 0)]
+    StoreNotFound(String),
+    #[fail(display = "Async error : {}", 0)]
+    AsyncError(String),
+    #[fail(display = "Expected version {}, found version {}", 0, 1)]
+    VersionConflict(u64, u64),
+    #[fail(display = "Audit log broken at entry {}", 0)]
+    AuditBroken(u64),
 }
 
 impl From<IoError> for MegadexDbError {
@@ -40,6 +58,12 @@ impl From<StoreError> for MegadexDbError {
     }
 }
 
+impl From<lmdb::Error> for MegadexDbError {
+    fn from(err: lmdb::Error) -> Self {
+        MegadexDbError::RkvError(StoreError::LmdbError(err))
+    }
+}
+
 impl From<Box<BinError>> for MegadexDbError {
     fn from(err: Box<BinError>) -> Self {
         MegadexDbError::BincodeError(err)
@@ -99,6 +123,69 @@ impl PartialEq for MegadexDbError {
                     false
                 }
             },
+            CodecError(e) => {
+                if let CodecError(s) = other {
+                    e == s
+                } else {
+                    false
+                }
+            },
+            CompressionError(e) => {
+                if let CompressionError(s) = other {
+                    e == s
+                } else {
+                    false
+                }
+            },
+            CryptoError(e) => {
+                if let CryptoError(s) = other {
+                    e == s
+                } else {
+                    false
+                }
+            },
+            EnvInUse(e) => {
+                if let EnvInUse(s) = other {
+                    e == s
+                } else {
+                    false
+                }
+            },
+            EnvOptionsDiffer(e) => {
+                if let EnvOptionsDiffer(s) = other {
+                    e == s
+                } else {
+                    false
+                }
+            },
+            StoreNotFound(e) => {
+                if let StoreNotFound(s) = other {
+                    e == s
+                } else {
+                    false
+                }
+            },
+            AsyncError(e) => {
+                if let AsyncError(s) = other {
+                    e == s
+                } else {
+                    false
+                }
+            },
+            VersionConflict(e, f) => {
+                if let VersionConflict(a, b) = other {
+                    e == a && f == b
+                } else {
+                    false
+                }
+            },
+            AuditBroken(e) => {
+                if let AuditBroken(s) = other {
+                    e == s
+                } else {
+                    false
+                }
+            },
         }
     }
 }
@@ -0,0 +1,272 @@
+use crate::backend::{
+    Backend,
+    ReadTxn,
+    WriteTxn,
+};
+use crate::codec::Codec;
+use crate::error::MegadexDbError;
+use crate::internal::{
+    self,
+    Section,
+};
+use crate::record::Record;
+use crate::subscribe::Change;
+use crate::MegadexDb;
+use serde::{
+    de::DeserializeOwned,
+    Serialize,
+};
+use std::sync::atomic::{
+    AtomicUsize,
+    Ordering,
+};
+use std::sync::mpsc::{
+    channel,
+    RecvTimeoutError,
+    Sender,
+};
+use std::sync::Arc;
+use std::thread::{
+    self,
+    JoinHandle,
+};
+use std::time::{
+    Duration,
+    SystemTime,
+    UNIX_EPOCH,
+};
+
+/// A field which tells when a record expires, see `Record::expires_at`
+pub trait Expiry {
+    fn deadline(&self) -> Option<SystemTime>;
+}
+
+impl Expiry for SystemTime {
+    fn deadline(&self) -> Option<SystemTime> {
+        Some(*self)
+    }
+}
+
+impl Expiry for Option<SystemTime> {
+    fn deadline(&self) -> Option<SystemTime> {
+        *self
+    }
+}
+
+/// Milliseconds since the epoch, the resolution of the deadlines and tombstones
+pub(crate) fn millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+pub(crate) fn now() -> u64 {
+    millis(SystemTime::now())
+}
+
+pub(crate) fn id_key(id: &[u8]) -> Vec<u8> {
+    let mut key = Vec::with_capacity(id.len() + 1);
+    key.push(b'i');
+    key.extend_from_slice(id);
+    key
+}
+
+pub(crate) fn time_key(time: u64, id: &[u8]) -> Vec<u8> {
+    let mut key = Vec::with_capacity(id.len() + 9);
+    key.push(b'd');
+    key.extend_from_slice(&time.to_be_bytes());
+    key.extend_from_slice(id);
+    key
+}
+
+pub(crate) fn read_time(bytes: &[u8]) -> Result<u64, MegadexDbError> {
+    let mut buf = [0; 8];
+    if bytes.len() < 8 {
+        return Err(MegadexDbError::ValueError("Malformed time entry".into()));
+    }
+    buf.copy_from_slice(&bytes[..8]);
+    Ok(u64::from_be_bytes(buf))
+}
+
+/// Replace the time kept for the encoded `id` in a section laid out like the deadlines
+pub(crate) fn set_time_txn<B: Backend>(
+    txn: &mut dyn WriteTxn<B>,
+    section: Section<B::Single>,
+    id: &[u8],
+    time: Option<u64>,
+) -> Result<(), MegadexDbError> {
+    if let Some(old) = section.get(&*txn, &id_key(id))? {
+        section.delete(txn, &time_key(read_time(&old)?, id))?;
+        section.delete(txn, &id_key(id))?;
+    }
+    if let Some(time) = time {
+        section.put(txn, &id_key(id), &time.to_be_bytes())?;
+        section.put(txn, &time_key(time, id), &[])?;
+    }
+    Ok(())
+}
+
+/// The ids whose time in `section` is at or before `until`
+pub(crate) fn due<B, X>(txn: &X, section: Section<B::Single>, until: u64) -> Result<Vec<Vec<u8>>, MegadexDbError>
+where
+    B: Backend,
+    X: ReadTxn<B> + ?Sized,
+{
+    let mut due = Vec::new();
+    section.scan(txn, Some(b"d"), &mut |key, _| {
+        if key.first() != Some(&b'd') || read_time(&key[1..])? > until {
+            return Ok(false);
+        }
+        due.push(key[9..].to_vec());
+        Ok(true)
+    })?;
+    Ok(due)
+}
+
+fn not_enabled() -> MegadexDbError {
+    MegadexDbError::ValueError("Expiry is not enabled for this collection".into())
+}
+
+impl<T, C, B> MegadexDb<T, C, B>
+where
+    T: Serialize + DeserializeOwned,
+    C: Codec,
+    B: Backend,
+{
+    /// Keep track of the deadlines of records, so that the records which are past their deadline
+    /// are hidden from `get` and the index lookups.
+    pub fn with_expiry(self) -> Result<Self, MegadexDbError> {
+        self.open_expiry(true)
+    }
+
+    pub(crate) fn open_expiry(mut self, create: bool) -> Result<Self, MegadexDbError> {
+        self.expiry = Some(Section::open(&self.db, internal::EXPIRY, create)?);
+        Ok(self)
+    }
+
+    /// Store an object of type T indexed by id, which is hidden once `ttl` has passed.
+    /// Requires `with_expiry`.
+    pub fn put_with_ttl<K: Serialize>(
+        &self,
+        id: &K,
+        obj: &T,
+        fields: &[(&str, &K)],
+        ttl: Duration,
+    ) -> Result<(), MegadexDbError> {
+        self.put_until(id, obj, fields, Some(SystemTime::now() + ttl))
+    }
+
+    /// Whether the record with the encoded `id` is past its deadline at `now`
+    pub(crate) fn expired<X>(&self, txn: &X, id: &[u8], now: u64) -> Result<bool, MegadexDbError>
+    where
+        X: ReadTxn<B> + ?Sized,
+    {
+        let section = match self.expiry {
+            Some(section) => section,
+            None => return Ok(false),
+        };
+        match section.get(txn, &id_key(id))? {
+            Some(deadline) => Ok(read_time(&deadline)? <= now),
+            None => Ok(false),
+        }
+    }
+
+    /// Replace the deadline of the record with the encoded `id` within an existing transaction.
+    /// Without `with_expiry` only `None` is accepted.
+    pub(crate) fn set_deadline_txn(
+        &self,
+        txn: &mut dyn WriteTxn<B>,
+        id: &[u8],
+        deadline: Option<SystemTime>,
+    ) -> Result<(), MegadexDbError> {
+        let section = match (self.expiry, deadline) {
+            (Some(section), _) => section,
+            (None, None) => return Ok(()),
+            (None, Some(_)) => return Err(not_enabled()),
+        };
+        set_time_txn(txn, section, id, deadline.map(millis))
+    }
+}
+
+impl<T, C, B> MegadexDb<T, C, B>
+where
+    T: Record + Serialize + DeserializeOwned,
+    C: Codec,
+    B: Backend,
+{
+    /// Delete the records which are past their deadline, along with their index entries.
+    /// Returns the number of records that were deleted.
+    pub fn purge_expired(&self) -> Result<usize, MegadexDbError> {
+        let section = self.expiry.ok_or_else(not_enabled)?;
+        let now = now();
+        let purged = self.db.write(|txn| {
+            let mut purged = Vec::new();
+            for id in due(&*txn, section, now)?.iter() {
+                purged.extend(self.del_record_txn(txn, id)?);
+            }
+            Ok(purged)
+        })?;
+        let changes: Vec<_> = purged.iter().map(Change::Deleted).collect();
+        self.subscribers.notify(&changes)?;
+        Ok(purged.len())
+    }
+}
+
+impl<T, C, B> MegadexDb<T, C, B>
+where
+    T: Record + Serialize + DeserializeOwned + Send + 'static,
+    C: Codec + Send + 'static,
+    B: Backend,
+{
+    /// Run `purge_expired` every `interval` on a thread of its own, until the `Purger` is dropped
+    pub fn spawn_purger(&self, interval: Duration) -> Result<Purger, MegadexDbError> {
+        self.expiry.ok_or_else(not_enabled)?;
+        let (stop, stopped) = channel();
+        let purged = Arc::new(AtomicUsize::new(0));
+        let failures = Arc::new(AtomicUsize::new(0));
+        let md = self.clone();
+        let (p, f) = (purged.clone(), failures.clone());
+        let thread = thread::Builder::new().name("megadex-purger".into()).spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                match md.purge_expired() {
+                    Ok(n) => p.fetch_add(n, Ordering::Relaxed),
+                    Err(_) => f.fetch_add(1, Ordering::Relaxed),
+                };
+            }
+        })?;
+        Ok(Purger {
+            stop: Some(stop),
+            thread: Some(thread),
+            purged,
+            failures,
+        })
+    }
+}
+
+/// A background thread running `purge_expired`, see `MegadexDb::spawn_purger`.
+/// Dropping it stops the thread.
+pub struct Purger {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+    purged: Arc<AtomicUsize>,
+    failures: Arc<AtomicUsize>,
+}
+
+impl Purger {
+    /// The number of records purged so far
+    pub fn purged(&self) -> usize {
+        self.purged.load(Ordering::Relaxed)
+    }
+
+    /// The number of sweeps which failed
+    pub fn failures(&self) -> usize {
+        self.failures.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Purger {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
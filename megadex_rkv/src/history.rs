@@ -0,0 +1,380 @@
+use crate::backend::{
+    Backend,
+    ReadTxn,
+    WriteTxn,
+};
+use crate::codec::Codec;
+use crate::envelope;
+use crate::error::MegadexDbError;
+use crate::internal::{
+    self,
+    Section,
+};
+use crate::expiry::{
+    self,
+    millis,
+};
+use crate::record::Record;
+use crate::MegadexDb;
+use serde::{
+    de::DeserializeOwned,
+    Serialize,
+};
+use std::borrow::Borrow;
+use std::time::{
+    Duration,
+    SystemTime,
+    UNIX_EPOCH,
+};
+
+const PRESENT: u8 = 1;
+const ABSENT: u8 = 0;
+
+/// How much history `with_history` keeps per record
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Retention {
+    /// Keep at most this many prior states of a record
+    pub max_count: Option<usize>,
+    /// Drop the states which were replaced longer ago than this
+    pub max_age: Option<Duration>,
+}
+
+impl Retention {
+    /// Keep every prior state
+    pub fn keep_all() -> Retention {
+        Retention::default()
+    }
+
+    /// Keep the last `n` prior states of each record
+    pub fn count(n: usize) -> Retention {
+        Retention {
+            max_count: Some(n),
+            max_age: None,
+        }
+    }
+
+    /// Keep the prior states which were replaced within `age`
+    pub fn age(age: Duration) -> Retention {
+        Retention {
+            max_count: None,
+            max_age: Some(age),
+        }
+    }
+}
+
+/// A prior state of a record, see `MegadexDb::history`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Revision<T> {
+    /// Counts up from 0 per record
+    pub seq: u64,
+    /// When this state was replaced by the next one
+    pub replaced_at: SystemTime,
+    /// The record, or `None` if it didn't exist, e.g. before it was first stored or after it was deleted
+    pub value: Option<T>,
+}
+
+fn prefix(id: &[u8]) -> Vec<u8> {
+    let mut key = Vec::with_capacity(id.len() + 20);
+    key.extend_from_slice(&(id.len() as u32).to_be_bytes());
+    key.extend_from_slice(id);
+    key
+}
+
+fn revision_key(id: &[u8], seq: u64, replaced_at: u64) -> Vec<u8> {
+    let mut key = prefix(id);
+    key.extend_from_slice(&seq.to_be_bytes());
+    key.extend_from_slice(&replaced_at.to_be_bytes());
+    key
+}
+
+fn malformed() -> MegadexDbError {
+    MegadexDbError::ValueError("Malformed history entry".into())
+}
+
+/// The length of the prefix of the record a key belongs to
+fn prefix_len(key: &[u8]) -> Result<usize, MegadexDbError> {
+    if key.len() < 4 {
+        return Err(malformed());
+    }
+    let mut len = [0; 4];
+    len.copy_from_slice(&key[..4]);
+    Ok(4 + u32::from_be_bytes(len) as usize)
+}
+
+/// Put the envelope header in front of the prior states which were kept without one,
+/// see `envelope::frame_collection`
+pub(crate) fn frame_history_txn<B: Backend>(
+    txn: &mut dyn WriteTxn<B>,
+    section: Section<B::Single>,
+) -> Result<(), MegadexDbError> {
+    let mut plain = Vec::new();
+    section.scan(&*txn, None, &mut |key, value| {
+        if let Some((&PRESENT, blob)) = value.split_first() {
+            if !is_head(key)? {
+                let mut value = vec![PRESENT];
+                value.extend(envelope::frame(0, None, blob));
+                plain.push((key.to_vec(), value));
+            }
+        }
+        Ok(true)
+    })?;
+    for (key, value) in plain.iter() {
+        section.put(txn, key, value)?;
+    }
+    Ok(())
+}
+
+/// Whether a key is the head of a record rather than one of its states
+fn is_head(key: &[u8]) -> Result<bool, MegadexDbError> {
+    Ok(key.len() == prefix_len(key)?)
+}
+
+/// Split a key into the prefix of its record, its sequence number and the time it was replaced
+fn split_key(key: &[u8]) -> Result<(&[u8], u64, u64), MegadexDbError> {
+    let end = prefix_len(key)?;
+    if key.len() != end + 16 {
+        return Err(malformed());
+    }
+    let (seq, time) = (expiry::read_time(&key[end..])?, expiry::read_time(&key[end + 8..])?);
+    Ok((&key[..end], seq, time))
+}
+
+/// Keys and values of the history section
+type Entries = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// The keys and values of the history of one record, oldest first
+fn revisions<B, X>(txn: &X, section: Section<B::Single>, id: &[u8]) -> Result<Entries, MegadexDbError>
+where
+    B: Backend,
+    X: ReadTxn<B> + ?Sized,
+{
+    let start = prefix(id);
+    let mut found = Vec::new();
+    section.scan(txn, Some(&start), &mut |key, value| {
+        if !key.starts_with(&start) {
+            return Ok(false);
+        }
+        if key.len() == start.len() {
+            return Ok(true);
+        }
+        found.push((key.to_vec(), value.to_vec()));
+        Ok(true)
+    })?;
+    Ok(found)
+}
+
+/// The keys among the history of one record, oldest first, which `retention` drops at `now`
+fn dropped<'k>(keys: &[&'k [u8]], retention: Retention, now: u64) -> Result<Vec<&'k [u8]>, MegadexDbError> {
+    let surplus = match retention.max_count {
+        Some(max) => keys.len().saturating_sub(max),
+        None => 0,
+    };
+    let oldest = match retention.max_age {
+        Some(age) => now.saturating_sub(age.as_millis() as u64),
+        None => 0,
+    };
+    let mut dropped = Vec::new();
+    for (i, key) in keys.iter().enumerate() {
+        if i < surplus || split_key(key)?.2 < oldest {
+            dropped.push(*key);
+        }
+    }
+    Ok(dropped)
+}
+
+fn not_enabled() -> MegadexDbError {
+    MegadexDbError::ValueError("History is not enabled for this collection".into())
+}
+
+impl<T, C, B> MegadexDb<T, C, B>
+where
+    T: Serialize + DeserializeOwned,
+    C: Codec,
+    B: Backend,
+{
+    /// Keep the prior states of every record that is written or deleted, within the limits of
+    /// `retention`.
+    pub fn with_history(self, retention: Retention) -> Result<Self, MegadexDbError> {
+        self.open_history(retention, true)
+    }
+
+    pub(crate) fn open_history(mut self, retention: Retention, create: bool) -> Result<Self, MegadexDbError> {
+        self.history = Some((Section::open(&self.db, internal::HISTORY, create)?, retention));
+        Ok(self)
+    }
+
+    /// The prior states of the record at the given id, oldest first
+    pub fn history<K>(&self, id: &K) -> Result<Vec<Revision<T>>, MegadexDbError>
+    where
+        T: Record,
+        T::Id: Borrow<K>,
+        K: Serialize + ?Sized,
+    {
+        let (section, _) = self.history.ok_or_else(not_enabled)?;
+        let keybytes = C::encode(id)?;
+        self.db.read(|txn| {
+            revisions(txn, section, &keybytes)?
+                .iter()
+                .map(|(key, value)| {
+                    let (_, seq, replaced_at) = split_key(key)?;
+                    Ok(Revision {
+                        seq,
+                        replaced_at: UNIX_EPOCH + Duration::from_millis(replaced_at),
+                        value: self.decode_revision(&keybytes, value)?,
+                    })
+                })
+                .collect()
+        })
+    }
+
+    /// The record at the given id as it was at `time`. Times after the last change give the record
+    /// as `get` does, times before the oldest state that is kept give that state.
+    pub fn get_as_of<K>(&self, id: &K, time: SystemTime) -> Result<Option<T>, MegadexDbError>
+    where
+        T: Record,
+        T::Id: Borrow<K>,
+        K: Serialize + ?Sized,
+    {
+        let (section, _) = self.history.ok_or_else(not_enabled)?;
+        let keybytes = C::encode(id)?;
+        let time = millis(time);
+        self.db.read(|txn| {
+            for (key, value) in revisions(txn, section, &keybytes)?.iter() {
+                if split_key(key)?.2 > time {
+                    return self.decode_revision(&keybytes, value);
+                }
+            }
+            self.get_live_txn(txn, &keybytes)
+        })
+    }
+
+    /// Drop the history which is past the retention limits, for every record.
+    /// Writes only apply the limits to the record they write.
+    /// Returns the number of states that were dropped.
+    pub fn prune_history(&self) -> Result<usize, MegadexDbError> {
+        let (section, retention) = self.history.ok_or_else(not_enabled)?;
+        let now = expiry::now();
+        self.db.write(|txn| {
+            let mut keys = Vec::new();
+            section.scan(&*txn, None, &mut |key, _| {
+                if !is_head(key)? {
+                    keys.push(key.to_vec());
+                }
+                Ok(true)
+            })?;
+            let mut doomed = Vec::new();
+            let mut start = 0;
+            while start < keys.len() {
+                let record = split_key(&keys[start])?.0;
+                let mut end = start;
+                while end < keys.len() && split_key(&keys[end])?.0 == record {
+                    end += 1;
+                }
+                let group: Vec<&[u8]> = keys[start..end].iter().map(|k| &k[..]).collect();
+                doomed.extend(dropped(&group, retention, now)?.into_iter().map(|k| k.to_vec()));
+                start = end;
+            }
+            for key in doomed.iter() {
+                section.delete(txn, key)?;
+            }
+            Ok(doomed.len())
+        })
+    }
+
+    fn decode_revision(&self, id: &[u8], value: &[u8]) -> Result<Option<T>, MegadexDbError> {
+        match value.split_first() {
+            Some((&PRESENT, blob)) => self.decode_value(id, blob).map(Some),
+            Some((&ABSENT, _)) => Ok(None),
+            _ => Err(malformed()),
+        }
+    }
+
+    /// Encrypt the prior states which aren't encrypted with the `current` key under it,
+    /// within an existing transaction. Returns the number of states that were rewritten.
+    #[cfg(feature = "encryption")]
+    pub(crate) fn rekey_history_txn(&self, txn: &mut dyn WriteTxn<B>, current: u32) -> Result<usize, MegadexDbError> {
+        let (section, _) = match self.history {
+            Some(history) => history,
+            None => return Ok(0),
+        };
+        let mut stale = Vec::new();
+        section.scan(&*txn, None, &mut |key, value| {
+            let blob = match value.split_first() {
+                Some((&PRESENT, blob)) if !is_head(key)? => blob,
+                _ => return Ok(true),
+            };
+            let id = &split_key(key)?.0[4..];
+            if let Some(blob) = self.reseal(id, blob, current)? {
+                let mut value = Vec::with_capacity(blob.len() + 1);
+                value.push(PRESENT);
+                value.extend(blob);
+                stale.push((key.to_vec(), value));
+            }
+            Ok(true)
+        })?;
+        for (key, value) in stale.iter() {
+            section.put(txn, key, value)?;
+        }
+        Ok(stale.len())
+    }
+
+    /// Keep the current state of the record with the encoded `id` in the history, before it is
+    /// replaced or deleted within an existing transaction, and apply the retention limits.
+    pub(crate) fn keep_history_txn(&self, txn: &mut dyn WriteTxn<B>, id: &[u8]) -> Result<(), MegadexDbError> {
+        let (section, retention) = match self.history {
+            Some(history) => history,
+            None => return Ok(()),
+        };
+        let now = expiry::now();
+        // a record behind a tombstone doesn't exist as far as reads go
+        let value = match txn.get(self.main, id)? {
+            Some(_) if self.tombstone(&*txn, id)?.is_some() => vec![ABSENT],
+            Some(blob) => {
+                let mut value = Vec::with_capacity(blob.len() + 1);
+                value.push(PRESENT);
+                value.extend(blob);
+                value
+            },
+            None => vec![ABSENT],
+        };
+        let head = prefix(id);
+        let seq = match section.get(&*txn, &head)? {
+            Some(next) => expiry::read_time(&next)?,
+            None => 0,
+        };
+        section.put(txn, &revision_key(id, seq, now), &value)?;
+        section.put(txn, &head, &(seq + 1).to_be_bytes())?;
+        if retention == Retention::keep_all() {
+            return Ok(());
+        }
+        // the states are dropped oldest first, so only the oldest ones need to be looked at
+        let oldest = retention.max_age.map(|age| now.saturating_sub(age.as_millis() as u64));
+        let mut doomed = Vec::new();
+        section.scan(&*txn, Some(&head), &mut |key, _| {
+            if !key.starts_with(&head) {
+                return Ok(false);
+            }
+            if key.len() == head.len() {
+                return Ok(true);
+            }
+            let (_, kept, replaced_at) = split_key(key)?;
+            let surplus = match retention.max_count {
+                Some(max) => seq - kept >= max as u64,
+                None => false,
+            };
+            let expired = match oldest {
+                Some(oldest) => replaced_at < oldest,
+                None => false,
+            };
+            if surplus || expired {
+                doomed.push(key.to_vec());
+                return Ok(true);
+            }
+            Ok(false)
+        })?;
+        for key in doomed.iter() {
+            section.delete(txn, key)?;
+        }
+        Ok(())
+    }
+}
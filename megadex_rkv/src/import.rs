@@ -0,0 +1,108 @@
+use crate::backend::Backend;
+use crate::codec::Codec;
+use crate::error::MegadexDbError;
+use crate::record::Record;
+use crate::subscribe::Change;
+use crate::MegadexDb;
+use serde::{
+    de::DeserializeOwned,
+    Serialize,
+};
+
+/// The number of records an import writes per transaction
+pub(crate) const IMPORT_BATCH: usize = 1000;
+
+/// What an import does with a record whose id is already taken
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Conflict {
+    /// Keep the existing record
+    Skip,
+    /// Replace the existing record, and its index entries
+    Overwrite,
+    /// Reject the record
+    Fail,
+}
+
+/// The number of records an import has written or skipped
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ImportStats {
+    pub inserted: usize,
+    pub replaced: usize,
+    pub skipped: usize,
+}
+
+/// A line of the input that could not be imported
+#[derive(Debug, PartialEq)]
+pub struct RowError {
+    pub line: usize,
+    pub error: MegadexDbError,
+}
+
+impl<T, C, B> MegadexDb<T, C, B>
+where
+    T: Record + Serialize + DeserializeOwned,
+    C: Codec,
+    B: Backend,
+{
+    /// Write a batch of `(line, record)` in one transaction. Records rejected by `Conflict::Fail`
+    /// are collected in `rejected` if it is supplied, otherwise the first one fails the batch.
+    pub(crate) fn import_batch(
+        &self,
+        batch: &[(usize, T)],
+        conflict: Conflict,
+        stats: &mut ImportStats,
+        rejected: Option<&mut Vec<RowError>>,
+    ) -> Result<(), MegadexDbError> {
+        let collect = rejected.is_some();
+        let watched = self.subscribers.active();
+        let (done, taken, written) = self.db.write(|txn| {
+            let mut done = ImportStats::default();
+            let mut taken = Vec::new();
+            let mut written = Vec::new();
+            for (line, obj) in batch.iter() {
+                let exists = txn.get(self.main, &C::encode(obj.id())?)?.is_some();
+                match (exists, conflict) {
+                    (true, Conflict::Skip) => done.skipped += 1,
+                    (true, Conflict::Fail) if collect => taken.push(*line),
+                    (true, Conflict::Fail) => return Err(id_taken(*line)),
+                    (true, Conflict::Overwrite) => {
+                        written.push((obj, self.put_record_txn(txn, obj)?));
+                        done.replaced += 1;
+                    },
+                    (false, _) => {
+                        written.push((obj, self.put_record_txn(txn, obj)?));
+                        done.inserted += 1;
+                    },
+                }
+            }
+            Ok((done, taken, written))
+        })?;
+        if watched {
+            let changes: Vec<_> = written
+                .iter()
+                .map(|(new, old)| match old {
+                    Some(old) => Change::Updated {
+                        old,
+                        new: *new,
+                    },
+                    None => Change::Inserted(*new),
+                })
+                .collect();
+            self.subscribers.notify(&changes)?;
+        }
+        stats.inserted += done.inserted;
+        stats.replaced += done.replaced;
+        stats.skipped += done.skipped;
+        if let Some(rejected) = rejected {
+            rejected.extend(taken.into_iter().map(|line| RowError {
+                line,
+                error: id_taken(line),
+            }));
+        }
+        Ok(())
+    }
+}
+
+fn id_taken(line: usize) -> MegadexDbError {
+    MegadexDbError::ValueError(format!("line {}: the id is already taken", line))
+}
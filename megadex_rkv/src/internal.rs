@@ -0,0 +1,99 @@
+use crate::backend::{
+    Backend,
+    ReadTxn,
+    Visitor,
+    WriteTxn,
+};
+use crate::error::MegadexDbError;
+
+/// The store which holds the bookkeeping of a collection: the format of its blobs, and the deadlines,
+/// tombstones, history, audit log and last auto id of the optional features. Each keeps its entries in a
+/// `Section` of its own, so they all take up one named store between them.
+pub(crate) const INTERNAL_STORE: &str = "_megadex_";
+
+/// The format of the blobs, see `envelope::frame_collection`. The `framed` key is there once every blob
+/// of the main store and the history starts with the envelope header.
+pub(crate) const FORMAT: u8 = b'f';
+/// The deadlines, see `MegadexDb::with_expiry`. There are two entries per record: `i` + id holds
+/// the deadline, and `d` + deadline + id orders the records by deadline for `purge_expired`.
+pub(crate) const EXPIRY: u8 = b'e';
+/// The tombstones, see `MegadexDb::with_soft_delete`.
+/// They are laid out like the deadlines, with the time of deletion in place of the deadline.
+pub(crate) const TOMBSTONES: u8 = b't';
+/// The prior states of records, see `MegadexDb::with_history`. Keys are the length of the id, the id,
+/// a sequence number and the time the state was replaced. Values are a tag byte, 1 followed by the blob
+/// of the record or 0 if it didn't exist. The head of each record, keyed by just the length of the id
+/// and the id, holds the next sequence number.
+pub(crate) const HISTORY: u8 = b'h';
+/// The audit log, see `MegadexDb::with_audit`. Entries are keyed by their sequence number,
+/// and the `head` key holds the sequence number and hash of the last entry.
+pub(crate) const AUDIT: u8 = b'a';
+/// The last id `insert_new` made up under the `last` key, see `MegadexDb::with_auto_id`
+pub(crate) const SEQUENCE: u8 = b's';
+
+/// The entries of the internal store whose keys start with one tag byte. The methods take
+/// and hand out keys without the tag, so a section reads like a store of its own.
+#[derive(Clone, Copy)]
+pub(crate) struct Section<S> {
+    store: S,
+    tag: u8,
+}
+
+impl<S: Copy> Section<S> {
+    pub fn open<B: Backend<Single = S>>(db: &B, tag: u8, create: bool) -> Result<Self, MegadexDbError> {
+        Ok(Section {
+            store: db.open_single(INTERNAL_STORE, create)?,
+            tag,
+        })
+    }
+
+    /// The section with the given tag in the same store
+    pub fn other(self, tag: u8) -> Self {
+        Section {
+            store: self.store,
+            tag,
+        }
+    }
+
+    fn key(&self, key: &[u8]) -> Vec<u8> {
+        let mut tagged = Vec::with_capacity(key.len() + 1);
+        tagged.push(self.tag);
+        tagged.extend_from_slice(key);
+        tagged
+    }
+
+    pub fn get<B, X>(&self, txn: &X, key: &[u8]) -> Result<Option<Vec<u8>>, MegadexDbError>
+    where
+        B: Backend<Single = S>,
+        X: ReadTxn<B> + ?Sized,
+    {
+        txn.get(self.store, &self.key(key))
+    }
+
+    /// Visit the entries of this section in key order, starting at `from` if given
+    pub fn scan<B, X>(&self, txn: &X, from: Option<&[u8]>, f: &mut Visitor) -> Result<(), MegadexDbError>
+    where
+        B: Backend<Single = S>,
+        X: ReadTxn<B> + ?Sized,
+    {
+        let tag = self.tag;
+        txn.scan(self.store, Some(&self.key(from.unwrap_or(&[]))), &mut |key, value| match key.split_first() {
+            Some((first, rest)) if *first == tag => f(rest, value),
+            _ => Ok(false),
+        })
+    }
+
+    pub fn put<B>(&self, txn: &mut dyn WriteTxn<B>, key: &[u8], value: &[u8]) -> Result<(), MegadexDbError>
+    where
+        B: Backend<Single = S>,
+    {
+        txn.put(self.store, &self.key(key), value)
+    }
+
+    pub fn delete<B>(&self, txn: &mut dyn WriteTxn<B>, key: &[u8]) -> Result<bool, MegadexDbError>
+    where
+        B: Backend<Single = S>,
+    {
+        txn.delete(self.store, &self.key(key))
+    }
+}
@@ -0,0 +1,97 @@
+use crate::backend::Backend;
+use crate::codec::Codec;
+use crate::error::MegadexDbError;
+use crate::expiry;
+use crate::import::{
+    Conflict,
+    ImportStats,
+    IMPORT_BATCH,
+};
+use crate::record::Record;
+use crate::MegadexDb;
+use serde::{
+    de::DeserializeOwned,
+    Serialize,
+};
+use serde_json::{
+    Map,
+    Value,
+};
+use std::io::{
+    BufRead,
+    Write,
+};
+
+fn json_err<E: std::fmt::Display>(line: usize, e: E) -> MegadexDbError {
+    MegadexDbError::CodecError(format!("line {}: {}", line, e))
+}
+
+impl<T, C, B> MegadexDb<T, C, B>
+where
+    T: Record + Serialize + DeserializeOwned,
+    C: Codec,
+    B: Backend,
+{
+    /// Write every record of the collection to `writer` as a line of JSON, `{"id":...,"value":...}`,
+    /// from a single read transaction. Returns the number of records written.
+    pub fn export_jsonl<W: Write>(&self, mut writer: W) -> Result<usize, MegadexDbError> {
+        let mut count = 0;
+        self.db.read(|txn| {
+            let now = expiry::now();
+            txn.scan(self.main, None, &mut |id, blob| {
+                if self.hidden(txn, id, now)? {
+                    return Ok(true);
+                }
+                let obj = self.decode_value(id, blob)?;
+                let mut line = Map::new();
+                line.insert("id".into(), serde_json::to_value(obj.id()).map_err(|e| json_err(count + 1, e))?);
+                line.insert("value".into(), serde_json::to_value(&obj).map_err(|e| json_err(count + 1, e))?);
+                serde_json::to_writer(&mut writer, &line).map_err(|e| json_err(count + 1, e))?;
+                writer.write_all(b"\n")?;
+                count += 1;
+                Ok(true)
+            })
+        })?;
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// Read records written by `export_jsonl` and store them along with their index entries.
+    /// Blank lines are ignored. The records are written in batches of one transaction each,
+    /// so if the import fails part way (or with `Conflict::Fail`) the batches before the
+    /// offending line are kept.
+    pub fn import_jsonl<R: BufRead>(&self, reader: R, conflict: Conflict) -> Result<ImportStats, MegadexDbError> {
+        let mut stats = ImportStats::default();
+        let mut batch = Vec::with_capacity(IMPORT_BATCH);
+        for (n, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            batch.push((n + 1, parse_line::<T, C>(n + 1, &line)?));
+            if batch.len() == IMPORT_BATCH {
+                self.import_batch(&batch, conflict, &mut stats, None)?;
+                batch.clear();
+            }
+        }
+        self.import_batch(&batch, conflict, &mut stats, None)?;
+        Ok(stats)
+    }
+}
+
+/// Parse a line of `export_jsonl`, checking that the id matches the record
+fn parse_line<T, C>(n: usize, line: &str) -> Result<T, MegadexDbError>
+where
+    T: Record + DeserializeOwned,
+    C: Codec,
+{
+    let mut line: Map<String, Value> = serde_json::from_str(line).map_err(|e| json_err(n, e))?;
+    let id: T::Id = serde_json::from_value(line.remove("id").ok_or_else(|| json_err(n, "missing id"))?)
+        .map_err(|e| json_err(n, e))?;
+    let obj: T = serde_json::from_value(line.remove("value").ok_or_else(|| json_err(n, "missing value"))?)
+        .map_err(|e| json_err(n, e))?;
+    if C::encode(&id)? != C::encode(obj.id())? {
+        return Err(json_err(n, "the id doesn't match the id of the value"));
+    }
+    Ok(obj)
+}
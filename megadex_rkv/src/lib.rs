@@ -1,260 +1,819 @@
+#[cfg(feature = "async")]
+mod async_db;
+mod audit;
+mod auto_id;
+mod backend;
+mod codec;
+mod compress;
+#[cfg(feature = "encryption")]
+mod crypto;
+#[cfg(feature = "csv")]
+mod csv_file;
+mod db;
+mod envelope;
 mod error;
+mod expiry;
+mod history;
+mod import;
+mod internal;
+#[cfg(feature = "json")]
+mod jsonl;
+mod mem;
+mod read_only;
+mod record;
+mod stats;
+mod subscribe;
+mod tombstone;
+mod version;
 
-use bincode;
+use rkv::store::multi::Iter as MdIter;
 use rkv::{
-    Manager,
-    SingleStore,
-    MultiStore,
-    Writer,
-    Reader,
     Readable,
     Rkv,
     Value,
-    StoreOptions,
-    StoreError,
 };
-use rkv::store::multi::Iter as MdIter;
-
 use serde::{
     de::DeserializeOwned,
 //    Deserialize,
     Serialize,
 };
+use std::borrow::Borrow;
 use std::collections::HashMap;
-use std::fs;
 use std::marker::PhantomData;
-use std::path::Path;
+use std::time::SystemTime;
 use std::sync::{
     Arc,
     RwLock,
 };
-use tempfile::Builder;
 
 #[cfg(test)]
 use serde_derive;
 
+#[cfg(feature = "async")]
+pub use crate::async_db::{
+    AsyncMegadexDb,
+    RecordStream,
+};
+pub use crate::audit::{
+    AuditEntry,
+    AuditOp,
+};
+pub use crate::auto_id::{
+    AutoId,
+    AutoRecord,
+    Ulid,
+};
+pub use crate::backend::{
+    Backend,
+    ReadTxn,
+    Visitor,
+    WriteTxn,
+};
+pub use crate::codec::{
+    Bincode,
+    Codec,
+};
+#[cfg(feature = "cbor")]
+pub use crate::codec::Cbor;
+#[cfg(feature = "json")]
+pub use crate::codec::Json;
+#[cfg(feature = "msgpack")]
+pub use crate::codec::MsgPack;
+pub use crate::compress::Compression;
+#[cfg(feature = "zstd")]
+pub use crate::compress::train_dictionary;
+#[cfg(feature = "encryption")]
+pub use crate::crypto::{
+    EncryptionKey,
+    KeyProvider,
+    StaticKeys,
+};
+#[cfg(feature = "csv")]
+pub use crate::csv_file::CsvImport;
+pub use crate::db::{
+    Db,
+    DbBuilder,
+    ResizeStats,
+    StoreInfo,
+    StoreKind,
+};
+pub use crate::envelope::{
+    is_framed,
+    plain_value,
+};
 pub use crate::error::MegadexDbError;
+pub use crate::expiry::{
+    Expiry,
+    Purger,
+};
+pub use crate::history::{
+    Retention,
+    Revision,
+};
+pub use crate::import::{
+    Conflict,
+    ImportStats,
+    RowError,
+};
+pub use crate::mem::{
+    MemDb,
+    MemMulti,
+    MemSingle,
+};
+pub use crate::read_only::ReadOnlyMegadexDb;
+pub use crate::record::{
+    Field,
+    Record,
+};
+pub use crate::stats::{
+    CollectionStats,
+    DbStats,
+    IndexStats,
+    PageStats,
+    StoreStats,
+};
+pub use crate::subscribe::Change;
+pub use crate::version::Stamp;
+use crate::envelope::Envelope;
+use crate::internal::Section;
+use crate::subscribe::Subscribers;
 
-/// A specialized database environment that is persisted to the provided directory.
-#[derive(Clone)]
-pub struct Db {
-    env: Arc<RwLock<Rkv>>,
-}
-
-impl Db {
-    /// Construct a new collection of indexes in a temp directory
-    /// This will create The main struct store for T and
-    /// the supporting secondary indexes to find the id for T
-    pub fn new_temp() -> Result<Db, MegadexDbError> {
-        let root = Builder::new().prefix("megadex").tempdir()?;
-        fs::create_dir_all(root.path())?;
-        let mut writer = Manager::singleton().write().expect("Failed to get Manager Singleton writer");
-        let env = writer.get_or_create(root.path(), Rkv::new)?;
-        Ok(Db {
-            env,
-        })
-    }
+/// For the bounds of the code generated by megadex_derive
+#[doc(hidden)]
+pub use serde;
 
-    /// Construct a new collection of indexes in the supplied directory,
-    /// or if one already exists, it will use it.
-    /// This will create The main struct store for T and
-    /// the supporting secondary indexes to find the id for T
-    pub fn new<'p, P: Into<&'p Path>>(dir: P) -> Result<Self, MegadexDbError> {
-        let mut writer = Manager::singleton().write().expect("Failed to get Manager Singleton writer");
-        let env = writer.get_or_create(dir, Rkv::new)?;
-        Ok(Db {
-            env,
-        })
-    }
+fn index_entry_missing(field: &str) -> MegadexDbError {
+    MegadexDbError::ValueError(format!("Index entry not found for field {}", field))
 }
 
+/// The name of the store holding the records of a collection. Its indexes are named after their fields.
+pub const MAIN_STORE: &str = "_main_";
+
 /// A specialized database table that is persisted to the provided directory. This will store
 /// structs which implement `Serialize` and `DeserializeOwned`.  It will also index
 /// those structs by any additional fields that you specify.
 ///
+/// Ids, index keys and values are all encoded with the codec `C`, which defaults to `Bincode`.
+/// Lookups take any key that the id or field `Borrow`s, so a `String` id can be looked up with a `str`
+/// and a `Vec<u8>` with a `[u8]`. Lookups by id need `T` to be a `Record`, lookups by field a `Field`.
+/// The encoded values can additionally be compressed, see `with_compression`, and
+/// encrypted, see `with_encryption`.
+///
+/// The data lives in the backend `B`, which defaults to the LMDB backed `Db`.
+/// Use `MemDb` for a collection that only lives in memory.
+///
+/// Every operation takes `&self`, LMDB serializes the writers. A `MegadexDb` is `Send` and `Sync`,
+/// and cloning it is cheap: the clones share the stores, settings and subscribers.
+///
 /// This is a sparse and rather specialized API as it is intended to be used with
 /// the megadex_derive crate.
-pub struct MegadexDb<T> {
-    env: Arc<RwLock<Rkv>>,
-    main: SingleStore,
-    indices: HashMap<String, MultiStore>,
-    p: PhantomData<T>,
+pub struct MegadexDb<T, C = Bincode, B: Backend = Db> {
+    db: B,
+    main: B::Single,
+    indices: Arc<HashMap<String, B::Multi>>,
+    /// Whether the blobs have the envelope header, see `envelope::frame_collection`
+    framed: bool,
+    compression: Option<Compression>,
+    #[cfg(feature = "encryption")]
+    keys: Option<Arc<dyn KeyProvider>>,
+    subscribers: Arc<Subscribers<T>>,
+    expiry: Option<Section<B::Single>>,
+    tombstones: Option<Section<B::Single>>,
+    show_deleted: bool,
+    history: Option<(Section<B::Single>, Retention)>,
+    audit: Option<(Section<B::Single>, Arc<str>)>,
+    actor: Arc<str>,
+    versions: Option<Stamp<T>>,
+    sequence: Option<Section<B::Single>>,
+    p: PhantomData<fn() -> T>,
+    codec: PhantomData<fn() -> C>,
+}
+
+impl<T, C, B: Backend> Clone for MegadexDb<T, C, B> {
+    fn clone(&self) -> Self {
+        MegadexDb {
+            db: self.db.clone(),
+            main: self.main,
+            indices: self.indices.clone(),
+            framed: self.framed,
+            compression: self.compression.clone(),
+            #[cfg(feature = "encryption")]
+            keys: self.keys.clone(),
+            subscribers: self.subscribers.clone(),
+            expiry: self.expiry,
+            tombstones: self.tombstones,
+            show_deleted: self.show_deleted,
+            history: self.history,
+            audit: self.audit.clone(),
+            actor: self.actor.clone(),
+            versions: self.versions,
+            sequence: self.sequence,
+            p: PhantomData,
+            codec: PhantomData,
+        }
+    }
+}
+
+impl<T, C: Codec> MegadexDb<T, C, Db> {
+    /// Fetch a handle to the underlying LMDB environment
+    pub fn get_env(&self) -> Arc<RwLock<Rkv>> {
+        self.db.env.clone()
+    }
+
+    /// Retrieve the exact type of ids that are indexed by the provided field
+    /// XXX Note that this will basically swallow deserialization and mismatchd type errors by
+    /// simpling excluding the result from the vector if it fails
+    #[deprecated(note = "use `ids_by_field`, which opens its own read transaction and hides expired and deleted records")]
+    pub fn get_ids_by_field<K, I, Txn>(&self, reader: &Txn, name: &str, key: &K) -> Result<Vec<I>, MegadexDbError>
+    where
+        K: Serialize,
+        I: DeserializeOwned,
+        Txn: Readable,
+    {
+        let keybytes = C::encode(key)?;
+        #[allow(deprecated)]
+        let ids = self.get_ids_by_field_raw(reader, name, &keybytes)?;
+        let mut found = Vec::new();
+        for item in ids.into_iter().flatten().flatten() {
+            if let (_, Some(Value::Blob(bytes))) = item {
+                found.extend(C::decode(bytes).ok());
+            }
+        }
+        Ok(found)
+    }
+
+    /// Retrieve an iterator for the raw bytes of ids that are indexed by the provided field
+    #[deprecated(note = "use `ids_by_field_txn`, which takes a transaction of any backend")]
+    pub fn get_ids_by_field_raw<'s, Txn: Readable>(
+        &self,
+        reader: &'s Txn,
+        name: &str,
+        key: &'s [u8],
+    ) -> Result<Option<MdIter<'s>>, MegadexDbError> {
+        let idstore = self.indices.get(name).ok_or_else(|| MegadexDbError::IndexUndefined(name.into()))?;
+        idstore.get(reader, key).map(Some).map_err(|e| e.into())
+    }
 }
 
-impl<T> MegadexDb<T>
+impl<T, C, B> MegadexDb<T, C, B>
 where
     T: Serialize + DeserializeOwned,
+    C: Codec,
+    B: Backend,
 {
-    pub fn new(db: Db, fields: &[&str]) -> Result<Self, MegadexDbError> {
-        let env = db.env;
-        let store = env.write().expect("failed to acquire env write lock").open_single("_main_", StoreOptions::create())?;
-
-        let mut md = MegadexDb {
-            env,
-            main: store,
-            indices: HashMap::new(),
+    pub fn new(db: B, fields: &[&str]) -> Result<Self, MegadexDbError> {
+        MegadexDb::open(db, fields, true)
+    }
+
+    pub(crate) fn open(db: B, fields: &[&str], create: bool) -> Result<Self, MegadexDbError> {
+        let main = db.open_single(MAIN_STORE, create)?;
+        let mut indices = HashMap::new();
+        for f in fields.iter() {
+            indices.insert((*f).into(), db.open_multi(f, create)?);
+        }
+        let framed = if create {
+            envelope::frame_collection(&db, main)?;
+            true
+        } else {
+            envelope::is_framed(&db)?
+        };
+
+        Ok(MegadexDb {
+            db,
+            main,
+            indices: Arc::new(indices),
+            framed,
+            compression: None,
+            #[cfg(feature = "encryption")]
+            keys: None,
+            subscribers: Arc::new(Subscribers::default()),
+            expiry: None,
+            tombstones: None,
+            show_deleted: false,
+            history: None,
+            audit: None,
+            actor: "".into(),
+            versions: None,
+            sequence: None,
             p: PhantomData,
+            codec: PhantomData,
+        })
+    }
+
+    /// Compress the values of this collection before they are written to the main store.
+    /// Use `Compression::Uncompressed` to stop compressing new records in a collection that
+    /// has been compressed with a dictionary, which is still needed to read the old ones.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Train a zstd dictionary from the records which are currently in this collection.
+    /// The result can be supplied to `Compression::zstd_with_dictionary`
+    #[cfg(feature = "zstd")]
+    pub fn train_dictionary(&self, max_size: usize) -> Result<Vec<u8>, MegadexDbError> {
+        let mut samples = Vec::new();
+        self.db.read(|txn| {
+            txn.scan(self.main, None, &mut |id, blob| {
+                let envelope = self.envelope(blob)?;
+                samples.push(self.unseal(id, envelope.flags, envelope.body)?);
+                Ok(true)
+            })
+        })?;
+        train_dictionary(&samples, max_size)
+    }
+
+    /// Encrypt the values of this collection with ChaCha20-Poly1305 before they are written
+    /// to the main store. Ids and index keys are not encrypted.
+    #[cfg(feature = "encryption")]
+    pub fn with_encryption(mut self, keys: Arc<dyn KeyProvider>) -> Self {
+        self.keys = Some(keys);
+        self
+    }
+
+    /// Rewrite every record, and every prior state kept by `with_history`, that isn't encrypted with
+    /// the current key of the `KeyProvider` under that key, so that retired keys can be discarded.
+    /// Returns the number of records and prior states that were rewritten.
+    #[cfg(feature = "encryption")]
+    pub fn rekey(&self) -> Result<usize, MegadexDbError> {
+        let keys = self.keys.clone().ok_or_else(|| MegadexDbError::CryptoError("Encryption is not enabled".into()))?;
+        let (current, _) = keys.current_key()?;
+        let main = self.main;
+        self.db.write(|txn| {
+            let mut stale = Vec::new();
+            txn.scan(main, None, &mut |id, blob| {
+                if let Some(blob) = self.reseal(id, blob, current)? {
+                    stale.push((id.to_vec(), blob));
+                }
+                Ok(true)
+            })?;
+            for (id, blob) in stale.iter() {
+                txn.put(main, id, blob)?;
+            }
+            Ok(stale.len() + self.rekey_history_txn(txn, current)?)
+        })
+    }
+
+    /// The blob of the record with the encoded `id` sealed again under the `current` key,
+    /// or `None` if it is encrypted with that key already
+    #[cfg(feature = "encryption")]
+    fn reseal(&self, id: &[u8], blob: &[u8], current: u32) -> Result<Option<Vec<u8>>, MegadexDbError> {
+        let envelope = self.envelope(blob)?;
+        if envelope.flags & envelope::ENCRYPTED != 0 && crypto::key_id(envelope.body)? == current {
+            return Ok(None);
+        }
+        let (flags, body) = self.seal(id, self.unseal(id, envelope.flags, envelope.body)?)?;
+        Ok(Some(envelope::frame(flags, envelope.version, &body)))
+    }
+
+    /// Compress and encrypt the encoded value according to the configuration of this collection.
+    /// Returns the envelope flags which describe the result along with it.
+    #[cfg_attr(not(feature = "encryption"), allow(unused_variables))]
+    fn seal(&self, id: &[u8], mut blob: Vec<u8>) -> Result<(u8, Vec<u8>), MegadexDbError> {
+        let mut flags = 0;
+        if let Some(ref c) = self.compression {
+            blob = c.compress(blob)?;
+            flags |= envelope::COMPRESSED;
+        }
+        #[cfg(feature = "encryption")]
+        {
+            if let Some(ref keys) = self.keys {
+                blob = crypto::seal(&**keys, id, &blob)?;
+                flags |= envelope::ENCRYPTED;
+            }
+        }
+        Ok((flags, blob))
+    }
+
+    /// Reverse `seal` for a body with the given envelope flags, returning the encoded value.
+    /// The flags rather than the configuration decide what is done, so records written with
+    /// other settings stay readable.
+    fn unseal(&self, id: &[u8], flags: u8, body: &[u8]) -> Result<Vec<u8>, MegadexDbError> {
+        let decrypted;
+        let body = if flags & envelope::ENCRYPTED != 0 {
+            decrypted = self.decrypt(id, body)?;
+            &decrypted[..]
+        } else {
+            body
         };
-        md.insert_fields(fields)?;
-        Ok(md)
+        if flags & envelope::COMPRESSED == 0 {
+            return Ok(body.to_vec());
+        }
+        self.compression.as_ref().unwrap_or(&Compression::Uncompressed).decompress(body)
     }
 
-    fn insert_fields(&mut self, fields: &[&str]) -> Result<(), MegadexDbError> {
-        for f in fields.into_iter() {
-            let store =
-                self.env.write().expect("failed to acquire env write lock").open_multi(f.to_owned(), StoreOptions::create())?;
-            self.indices.insert((*f).into(), store);
+    #[cfg(feature = "encryption")]
+    fn decrypt(&self, id: &[u8], body: &[u8]) -> Result<Vec<u8>, MegadexDbError> {
+        match self.keys {
+            Some(ref keys) => crypto::open(&**keys, id, body),
+            None => Err(MegadexDbError::CryptoError("Record is encrypted, but no keys are configured".into())),
         }
-        Ok(())
     }
 
-    /// Fetch a handle to the underlying LMDB environment
-    pub fn get_env(&self) -> Arc<RwLock<Rkv>> {
-        self.env.clone()
+    #[cfg(not(feature = "encryption"))]
+    fn decrypt(&self, _id: &[u8], _body: &[u8]) -> Result<Vec<u8>, MegadexDbError> {
+        Err(MegadexDbError::CryptoError("Record is encrypted, but the encryption feature is not enabled".into()))
     }
 
-    /// Retrieve T from the database at the given id.
-    /// Returns `None` if there is no value present for the id
-    pub fn get<K: Serialize>(&self, id: &K) -> Result<Option<T>, MegadexDbError> {
-        let envlock = self.env.read().expect("Failed to acquire read lock");
-        let reader = envlock.read()?;
-        if let Some(Value::Blob(blob)) = self.main.get(&reader, &bincode::serialize(id).map_err(MegadexDbError::from)?)? {
-            bincode::deserialize(&blob).map(Some).map_err(|e| e.into())
+    /// Take a blob of the main store apart
+    pub(crate) fn envelope<'b>(&self, blob: &'b [u8]) -> Result<Envelope<'b>, MegadexDbError> {
+        if self.framed {
+            Envelope::parse(blob)
         } else {
-            Ok(None)
+            Ok(Envelope::plain(blob))
         }
     }
 
+    fn encode_value(&self, id: &[u8], obj: &T) -> Result<(u8, Vec<u8>), MegadexDbError> {
+        self.seal(id, C::encode(obj)?)
+    }
+
+    fn decode_value(&self, id: &[u8], blob: &[u8]) -> Result<T, MegadexDbError> {
+        let envelope = self.envelope(blob)?;
+        let mut obj = C::decode(&self.unseal(id, envelope.flags, envelope.body)?)?;
+        if let Some(stamp) = self.versions {
+            stamp(&mut obj, self.version_of(&envelope));
+        }
+        Ok(obj)
+    }
+
+    /// Retrieve T by its encoded id within an existing transaction
+    fn get_txn<X>(&self, txn: &X, id: &[u8]) -> Result<Option<T>, MegadexDbError>
+    where
+        X: ReadTxn<B> + ?Sized,
+    {
+        match txn.get(self.main, id)? {
+            Some(blob) => self.decode_value(id, &blob).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Whether the record with the encoded `id` has expired or been deleted at `now`
+    fn hidden<X>(&self, txn: &X, id: &[u8], now: u64) -> Result<bool, MegadexDbError>
+    where
+        X: ReadTxn<B> + ?Sized,
+    {
+        Ok(self.expired(txn, id, now)? || self.deleted(txn, id)?)
+    }
+
+    /// `get_txn`, unless the record has expired or been deleted
+    fn get_live_txn<X>(&self, txn: &X, id: &[u8]) -> Result<Option<T>, MegadexDbError>
+    where
+        X: ReadTxn<B> + ?Sized,
+    {
+        if self.hidden(txn, id, expiry::now())? {
+            return Ok(None);
+        }
+        self.get_txn(txn, id)
+    }
+
+    /// Retrieve T from the database at the given id.
+    /// Returns `None` if there is no value present for the id
+    pub fn get<K>(&self, id: &K) -> Result<Option<T>, MegadexDbError>
+    where
+        T: Record,
+        T::Id: Borrow<K>,
+        K: Serialize + ?Sized,
+    {
+        let keybytes = C::encode(id)?;
+        self.db.read(|txn| self.get_live_txn(txn, &keybytes))
+    }
+
     /// Retrieve all objects that are indexed by the provided field
-    /// TODO This is ugly.. find a good way to refactor the handling of iterators of results of
-    /// options of values
-    pub fn get_by_field<K: Serialize>(&self, name: &str, key: &K) -> Result<Vec<T>, MegadexDbError> {
-        let keybytes = bincode::serialize(key).map_err(MegadexDbError::from)?;
-        let envlock = self.env.read().expect("Failed to acquire read lock");
-        let reader = envlock.read()?;
-        let res = self.get_ids_by_field_raw(&reader, name, &keybytes)?;
-        res.map(|ids| ids.map(|id| match id { 
-            Ok((_, Some(Value::Blob(id)))) => {
-                match self.main.get(&reader, id) {
-                    Ok(Some(Value::Blob(o))) => bincode::deserialize(&o).map_err(|e| e.into()),
-                    Ok(None) => Err(MegadexDbError::ValueError("Object not found for id".into())),
-                    e => Err(MegadexDbError::InvalidType("Blob".into(), format!("{:?}", e))),
-                }
-            },
-            Ok((_, Some(_))) => Err(MegadexDbError::ValueError("Invalid Field Index Type".into())),
-            Ok((_, None)) => Err(MegadexDbError::ValueError("Object not found for id".into())),
-            Err(_) => Err(MegadexDbError::ValueError("Object not found for id".into())),
-        })).map_or(Ok(Vec::new()), |v| v.collect::<Result<Vec<T>, _>>())
+    pub fn get_by_field<F, K>(&self, field: Field<T, F>, key: &K) -> Result<Vec<T>, MegadexDbError>
+    where
+        F: Borrow<K>,
+        K: Serialize + ?Sized,
+    {
+        self.get_by_key(field.name(), &C::encode(key)?)
     }
-    
+
+    /// Retrieve all objects that are indexed by the encoded key of a field
+    fn get_by_key(&self, name: &str, keybytes: &[u8]) -> Result<Vec<T>, MegadexDbError> {
+        self.db.read(|txn| {
+            self.ids_by_field_txn(txn, name, keybytes)?
+                .into_iter()
+                .map(|id| match txn.get(self.main, &id)? {
+                    Some(blob) => self.decode_value(&id, &blob),
+                    None => Err(MegadexDbError::ValueError("Object not found for id".into())),
+                })
+                .collect()
+        })
+    }
+
     /// Retrieve the exact type of ids that are indexed by the provided field
     /// XXX Note that this will basically swallow deserialization and mismatchd type errors by
     /// simpling excluding the result from the vector if it fails
-    pub fn get_ids_by_field<'s, K, I, Txn> (
-        &self,
-        reader: &Txn,
-        name: &str,
-        key: &'s K,
-    ) -> Result<Vec<I>, MegadexDbError> 
-        where
-            K: Serialize,
-            I: DeserializeOwned,
-            Txn: Readable,
+    pub fn ids_by_field<F, K, I>(&self, field: Field<T, F>, key: &K) -> Result<Vec<I>, MegadexDbError>
+    where
+        F: Borrow<K>,
+        K: Serialize + ?Sized,
+        I: DeserializeOwned,
+    {
+        let keybytes = C::encode(key)?;
+        self.db.read(|txn| {
+            Ok(self.ids_by_field_txn(txn, field.name(), &keybytes)?.iter().filter_map(|id| C::decode(id).ok()).collect())
+        })
+    }
+
+    fn index(&self, name: &str) -> Result<B::Multi, MegadexDbError> {
+        self.indices.get(name).copied().ok_or_else(|| MegadexDbError::IndexUndefined(name.into()))
+    }
+
+    /// Retrieve the raw bytes of the ids that are indexed by the provided field,
+    /// within an existing transaction
+    pub fn ids_by_field_txn<X>(&self, txn: &X, name: &str, key: &[u8]) -> Result<Vec<Vec<u8>>, MegadexDbError>
+    where
+        X: ReadTxn<B> + ?Sized,
     {
-        let unpack = |obj : Result<(_, Option<Value>), StoreError> | -> Option<I> {
-            match obj {
-                Ok((_, Some(Value::Blob(bytes)))) => {
-                    bincode::deserialize(bytes).map_err(|e : bincode::Error| -> MegadexDbError {  e.into() }).ok()
-                }, 
-                Ok((_, Some(_))) => None,
-                Ok((_, None)) => None,
-                Err(_) => None,
+        let ids = txn.get_dup(self.index(name)?, key)?;
+        if self.expiry.is_none() && self.tombstones.is_none() {
+            return Ok(ids);
+        }
+        let now = expiry::now();
+        let mut live = Vec::with_capacity(ids.len());
+        for id in ids {
+            if !self.hidden(txn, &id, now)? {
+                live.push(id);
             }
-        };
-        match self.get_ids_by_field_raw(reader, name, &bincode::serialize(key).map_err(MegadexDbError::from)?)? {
-            None => Ok(Vec::new()),
-            Some(iter) => Ok(iter.map(unpack).flatten().collect::<Vec<I>>())
         }
+        Ok(live)
     }
 
-    /// Retrieve an iterator for the raw bytes of ids that are indexed by the provided field
-    pub fn get_ids_by_field_raw<'s, Txn: Readable>(
+    /// Store an object of type T indexed by id
+    pub fn put<K: Serialize>(&self, id: &K, obj: &T, fields: &[(&str, &K)]) -> Result<(), MegadexDbError> {
+        self.put_until(id, obj, fields, None)
+    }
+
+    /// Store an object of type T indexed by id, which expires at `deadline` if there is one.
+    /// A deadline requires `with_expiry`.
+    pub fn put_until<K: Serialize>(
         &self,
-        reader: &'s Txn,
-        name: &str,
-        key: &'s [u8],
-    ) -> Result<Option<MdIter<'s>>, MegadexDbError> {
-        let idstore = self.indices.get(name).ok_or_else(|| MegadexDbError::IndexUndefined(name.into()))?;
-        idstore.get(reader, key).map(Some).map_err(|e| e.into()) 
+        id: &K,
+        obj: &T,
+        fields: &[(&str, &K)],
+        deadline: Option<SystemTime>,
+    ) -> Result<(), MegadexDbError> {
+        self.write_value(id, obj, fields, deadline, None)
     }
 
-    /// Store an object of type T indexed by id
-    pub fn put<K: Serialize>(&mut self, id: &K, obj: &T, fields: &[(&str, &K)]) -> Result<(), MegadexDbError> {
-        let keybytes = bincode::serialize(id).map_err(MegadexDbError::from)?;
-        let env = self.env.clone();
-        let envlock = env.read().expect("Failed to acquire read lock");
-        let mut writer = envlock.write()?;
-        self.put_id_txn(&mut writer, &keybytes, obj)?;
-        for (field, key) in fields.iter() {
-            self.put_field_txn(&mut writer, field, key, &keybytes)?;
-        }
-        writer.commit().map_err(|e| e.into())
-    }
-
-    fn put_id_txn<'s>(
-        &mut self,
-        writer: &mut Writer,
-        id: &'s [u8],
+    /// Store an object, checking the version of the record it replaces against `expected` if given
+    fn write_value<K: Serialize>(
+        &self,
+        id: &K,
         obj: &T,
+        fields: &[(&str, &K)],
+        deadline: Option<SystemTime>,
+        expected: Option<u64>,
     ) -> Result<(), MegadexDbError> {
-        let blob = bincode::serialize(obj)?;
-        self.main.put(writer, id, &Value::Blob(&blob)).map_err(|e| e.into())
+        let keybytes = C::encode(id)?;
+        let (flags, body) = self.encode_value(&keybytes, obj)?;
+        let watched = self.subscribers.active();
+        let old = self.db.write(|txn| {
+            let version = self.next_version_txn(txn, &keybytes, expected)?;
+            let old = if watched { self.get_txn(txn, &keybytes)? } else { None };
+            self.keep_history_txn(txn, &keybytes)?;
+            self.audit_txn(txn, AuditOp::Put, &keybytes)?;
+            self.put_blob_txn(txn, &keybytes, flags, &body, version)?;
+            self.clear_tombstone_txn(txn, &keybytes)?;
+            for (field, key) in fields.iter() {
+                self.put_field_txn(txn, field, key, &keybytes)?;
+            }
+            self.set_deadline_txn(txn, &keybytes, deadline)?;
+            Ok(old)
+        })?;
+        if watched {
+            self.subscribers.notify(&[match old {
+                Some(ref old) => Change::Updated {
+                    old,
+                    new: obj,
+                },
+                None => Change::Inserted(obj),
+            }])?;
+        }
+        Ok(())
     }
 
-    fn put_field_txn<'s, K: Serialize>(
-        &mut self,
-        writer: &mut Writer,
+    fn put_field_txn<K: Serialize>(
+        &self,
+        txn: &mut dyn WriteTxn<B>,
         field: &str,
-        key: &'s K,
+        key: &K,
         id: &[u8],
     ) -> Result<(), MegadexDbError> {
-        let idstore = self.indices.get_mut(field).ok_or_else(|| MegadexDbError::IndexUndefined(field.into()))?;
-        let keybytes = bincode::serialize(key)?;
-        idstore.put(writer, keybytes, &Value::Blob(id)).map_err(|e| e.into())
+        txn.put_dup(self.index(field)?, &C::encode(key)?, id)
     }
 
     /// Delete an object and all of its indexed fields.
     /// Note that the obj, `T` must be in the exact state in which it was put into the DB
     /// for it to be successfully deleted.
-    pub fn del<K: Serialize>(&mut self, id: &K, fields: &[(&str, &K)]) -> Result<(), MegadexDbError> {
-        let keybytes = bincode::serialize(id).map_err(MegadexDbError::from)?;
-        let env = self.env.clone();
-        let envlock = env.read().expect("Failed to acquire read lock");
-        let mut writer = envlock.write()?;
-        self.main.delete(&mut writer, &keybytes).map_err(|e| -> MegadexDbError { e.into() })?;
-        for (field, key) in fields {
-            self.del_field_txn(&mut writer, field, key, &keybytes)?;
+    /// With `with_soft_delete` the object is only marked as deleted. Its index entries stay until it is
+    /// purged, but `fields` must still match them.
+    pub fn del<K: Serialize>(&self, id: &K, fields: &[(&str, &K)]) -> Result<(), MegadexDbError> {
+        let keybytes = C::encode(id)?;
+        let watched = self.subscribers.active();
+        let old = self.db.write(|txn| {
+            let old = if watched { self.get_txn(txn, &keybytes)? } else { None };
+            if self.tombstones.is_some() {
+                if !self.tombstone_txn(txn, &keybytes)? {
+                    return Err(MegadexDbError::ValueError("Object not found for id".into()));
+                }
+                for (field, key) in fields {
+                    if !txn.get_dup(self.index(field)?, &C::encode(key)?)?.contains(&keybytes) {
+                        return Err(index_entry_missing(field));
+                    }
+                }
+                return Ok(old);
+            }
+            self.keep_history_txn(txn, &keybytes)?;
+            self.audit_txn(txn, AuditOp::Delete, &keybytes)?;
+            if !txn.delete(self.main, &keybytes)? {
+                return Err(MegadexDbError::ValueError("Object not found for id".into()));
+            }
+            for (field, key) in fields {
+                self.del_field_txn(txn, field, key, &keybytes)?;
+            }
+            self.set_deadline_txn(txn, &keybytes, None)?;
+            Ok(old)
+        })?;
+        if let Some(ref old) = old {
+            self.subscribers.notify(&[Change::Deleted(old)])?;
         }
-
-        writer.commit().map_err(|e| e.into())
+        Ok(())
     }
 
     fn del_field_txn<K: Serialize>(
-        &mut self,
-        writer: &mut Writer,
+        &self,
+        txn: &mut dyn WriteTxn<B>,
         field: &str,
         key: &K,
         id: &[u8],
     ) -> Result<(), MegadexDbError> {
-        let idstore = self.indices.get_mut(field).ok_or_else(|| MegadexDbError::IndexUndefined(field.into()))?;
-        let keybytes = bincode::serialize(key)?;
-        idstore.delete(writer, keybytes, &Value::Blob(id)).map_err(|e| e.into())
+        if txn.delete_dup(self.index(field)?, &C::encode(key)?, id)? {
+            Ok(())
+        } else {
+            Err(index_entry_missing(field))
+        }
+    }
+}
+
+impl<T, C, B> MegadexDb<T, C, B>
+where
+    T: Record + Serialize + DeserializeOwned,
+    C: Codec,
+    B: Backend,
+{
+    /// Store `obj` under its own id within an existing transaction, and move its index
+    /// entries over from the record it replaces. Returns the replaced record.
+    fn put_record_txn(&self, txn: &mut dyn WriteTxn<B>, obj: &T) -> Result<Option<T>, MegadexDbError> {
+        Ok(self.put_record_checked_txn(txn, obj, None)?.0)
+    }
+
+    /// `put_record_txn`, checking the version of the record it replaces against `expected` if given.
+    /// Returns the replaced record and the version that was written.
+    fn put_record_checked_txn(
+        &self,
+        txn: &mut dyn WriteTxn<B>,
+        obj: &T,
+        expected: Option<u64>,
+    ) -> Result<(Option<T>, u64), MegadexDbError> {
+        let id = C::encode(obj.id())?;
+        let version = self.next_version_txn(txn, &id, expected)?;
+        let old = self.get_txn(txn, &id)?;
+        if let Some(ref old) = old {
+            for (field, key) in old.index_keys::<C>()? {
+                txn.delete_dup(self.index(field)?, &key, &id)?;
+            }
+        }
+        self.keep_history_txn(txn, &id)?;
+        self.audit_txn(txn, AuditOp::Put, &id)?;
+        let (flags, body) = self.encode_value(&id, obj)?;
+        self.put_blob_txn(txn, &id, flags, &body, version)?;
+        self.clear_tombstone_txn(txn, &id)?;
+        for (field, key) in obj.index_keys::<C>()? {
+            txn.put_dup(self.index(field)?, &key, &id)?;
+        }
+        self.set_deadline_txn(txn, &id, obj.expires_at())?;
+        Ok((old, version))
+    }
+
+    /// Delete the record with the encoded `id` and its index entries within an existing transaction.
+    /// Returns the deleted record.
+    fn del_record_txn(&self, txn: &mut dyn WriteTxn<B>, id: &[u8]) -> Result<Option<T>, MegadexDbError> {
+        self.drop_record_txn(txn, id, AuditOp::Delete)
+    }
+
+    /// `del_record_txn`, logging the deletion as `op`
+    fn drop_record_txn(&self, txn: &mut dyn WriteTxn<B>, id: &[u8], op: AuditOp) -> Result<Option<T>, MegadexDbError> {
+        let old = self.get_txn(txn, id)?;
+        if let Some(ref old) = old {
+            for (field, key) in old.index_keys::<C>()? {
+                txn.delete_dup(self.index(field)?, &key, id)?;
+            }
+            self.keep_history_txn(txn, id)?;
+            self.audit_txn(txn, op, id)?;
+            txn.delete(self.main, id)?;
+        }
+        self.set_deadline_txn(txn, id, None)?;
+        self.clear_tombstone_txn(txn, id)?;
+        Ok(old)
+    }
+
+    /// Store `obj` under its own id, moving its index entries over from the record it replaces.
+    /// Returns the replaced record.
+    pub fn put_record(&self, obj: &T) -> Result<Option<T>, MegadexDbError> {
+        self.put_record_checked(obj, None)
+    }
+
+    /// `put_record_checked_txn` in a transaction of its own, notifying the subscribers
+    fn put_record_checked(&self, obj: &T, expected: Option<u64>) -> Result<Option<T>, MegadexDbError> {
+        let (old, _) = self.db.write(|txn| self.put_record_checked_txn(txn, obj, expected))?;
+        if self.subscribers.active() {
+            self.subscribers.notify(&[match old {
+                Some(ref old) => Change::Updated {
+                    old,
+                    new: obj,
+                },
+                None => Change::Inserted(obj),
+            }])?;
+        }
+        Ok(old)
+    }
+
+    /// Read the record at the given id, apply `f` to it and write it back along with its index
+    /// entries, all in one write transaction. Returns the new value, or `None` if there is no such record.
+    /// `f` may be called again if the transaction has to be retried, and must not change the id.
+    pub fn update<K, F>(&self, id: &K, f: F) -> Result<Option<T>, MegadexDbError>
+    where
+        T::Id: Borrow<K>,
+        K: Serialize + ?Sized,
+        F: FnMut(&mut T),
+    {
+        self.update_record(&C::encode(id)?, f)
+    }
+
+    /// `update` by the encoded id
+    fn update_record<F: FnMut(&mut T)>(&self, keybytes: &[u8], mut f: F) -> Result<Option<T>, MegadexDbError> {
+        let updated = self.db.write(|txn| {
+            let mut obj = match self.get_live_txn(txn, keybytes)? {
+                Some(obj) => obj,
+                None => return Ok(None),
+            };
+            f(&mut obj);
+            if C::encode(obj.id())? != keybytes {
+                return Err(MegadexDbError::ValueError("An update can not change the id of a record".into()));
+            }
+            let (old, version) = self.put_record_checked_txn(txn, &obj, None)?;
+            if let Some(stamp) = self.versions {
+                stamp(&mut obj, version);
+            }
+            Ok(Some((obj, old)))
+        })?;
+        match updated {
+            Some((new, old)) => {
+                if let Some(ref old) = old {
+                    self.subscribers.notify(&[Change::Updated {
+                        old,
+                        new: &new,
+                    }])?;
+                }
+                Ok(Some(new))
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Delete the record with the given id along with the index entries it was stored with.
+    /// Returns the deleted record.
+    pub fn remove<K>(&self, id: &K) -> Result<Option<T>, MegadexDbError>
+    where
+        T::Id: Borrow<K>,
+        K: Serialize + ?Sized,
+    {
+        self.del_record(&C::encode(id)?)
+    }
+
+    /// `del_record_txn` in a transaction of its own, notifying the subscribers.
+    /// With `with_soft_delete` the record is only marked as deleted.
+    fn del_record(&self, id: &[u8]) -> Result<Option<T>, MegadexDbError> {
+        let old = self.db.write(|txn| {
+            if self.tombstones.is_none() {
+                return self.del_record_txn(txn, id);
+            }
+            let old = self.get_txn(txn, id)?;
+            Ok(if self.tombstone_txn(txn, id)? { old } else { None })
+        })?;
+        if let Some(ref old) = old {
+            self.subscribers.notify(&[Change::Deleted(old)])?;
+        }
+        Ok(old)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::internal;
     use serde_derive::{
         Deserialize,
         Serialize,
@@ -267,10 +826,26 @@ mod tests {
         b: String,
     }
 
-    #[test]
-    fn it_works() {
-        let db = Db::new_temp().unwrap();
-        let mut md: MegadexDb<Weee> = MegadexDb::new(db, &["a", "b"][..]).unwrap();
+    const B: Field<Weee, String> = Field::new("b");
+
+    impl Record for Weee {
+        type Id = String;
+
+        fn fields() -> &'static [&'static str] {
+            &["b"]
+        }
+
+        fn id(&self) -> &String {
+            &self.id
+        }
+
+        fn index_keys<C: Codec>(&self) -> Result<Vec<(&'static str, Vec<u8>)>, MegadexDbError> {
+            Ok(vec![("b", C::encode(&self.b)?)])
+        }
+    }
+
+    fn check_backend<B: Backend>(db: B) {
+        let md: MegadexDb<Weee, Bincode, B> = MegadexDb::new(db, &["a", "b"][..]).unwrap();
         let w = Weee {
             id: "wat".into(),
             a: 42,
@@ -291,10 +866,10 @@ mod tests {
         let lala = md.get(&x.id).unwrap();
         assert_eq!(Some(x.clone()), lala);
 
-        let ha = md.get_by_field("b", &w.b).unwrap();
+        let ha = md.get_by_field(B, &w.b).unwrap();
         assert_eq!(ha, vec![x.clone(), w.clone()]);
 
-        let res = md.get_by_field("c", &w.b).err().unwrap();
+        let res = md.get_by_field(Field::<Weee, String>::new("c"), &w.b).err().unwrap();
         assert_eq!(MegadexDbError::IndexUndefined("c".into()), res);
 
         md.del(&w.id, &[("b".into(), &w.b)]).unwrap();
@@ -302,7 +877,764 @@ mod tests {
         let lala = md.get(&w.id).unwrap();
         assert_eq!(None, lala);
 
-        let ha = md.get_by_field("b", &w.b.as_bytes()).unwrap();
+        let ha = md.get_by_field(B, w.b.as_str()).unwrap();
         assert_eq!(ha, vec![x.clone()]);
+
+        // a failed delete must leave the record and its index entries untouched
+        let res = md.del(&x.id, &[("b", &"nope".to_string())]).err().unwrap();
+        assert_eq!(MegadexDbError::ValueError("Index entry not found for field b".into()), res);
+        assert_eq!(Some(x.clone()), md.get(&x.id).unwrap());
+        let ids: Vec<String> = md.ids_by_field(B, &x.b).unwrap();
+        assert_eq!(ids, vec![x.id.clone()]);
+    }
+
+    #[test]
+    fn it_works() {
+        check_backend(Db::new_temp().unwrap());
+    }
+
+    #[test]
+    fn in_memory() {
+        check_backend(MemDb::new());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn reader_lookups() {
+        let md: MegadexDb<Weee> = MegadexDb::new(Db::new_temp().unwrap(), &["b"][..]).unwrap();
+        let w = Weee {
+            id: "wat".into(),
+            a: 42,
+            b: "lalalala".into(),
+        };
+        md.put(&w.id, &w, &[("b", &w.b)]).unwrap();
+
+        let env = md.get_env();
+        let envlock = env.read().unwrap();
+        let reader = envlock.read().unwrap();
+        let ids: Vec<String> = md.get_ids_by_field(&reader, "b", &w.b).unwrap();
+        assert_eq!(vec![w.id.clone()], ids);
+        assert_eq!(1, md.get_ids_by_field_raw(&reader, "b", &bincode::serialize(&w.b).unwrap()).unwrap().unwrap().count());
+    }
+
+    #[test]
+    fn builder() {
+        let fields = ["a", "b", "c", "d", "e", "f"];
+        let res = MegadexDb::<Weee>::new(Db::new_temp().unwrap(), &fields[..]).err().unwrap();
+        assert!(matches!(res, MegadexDbError::RkvError(rkv::StoreError::LmdbError(lmdb::Error::DbsFull))));
+
+        let dir = tempfile::Builder::new().prefix("megadex").tempdir().unwrap();
+        let builder = Db::builder().max_dbs(8).map_size(1 << 24).no_meta_sync(true).mode(0o600);
+        let db = builder.open(dir.path()).unwrap();
+        let md: MegadexDb<Weee> = MegadexDb::new(db, &fields[..]).unwrap();
+        let w = Weee {
+            id: "wat".into(),
+            a: 42,
+            b: "lalalala".into(),
+        };
+        md.put(&w.id, &w, &[("b", &w.b)]).unwrap();
+        assert_eq!(Some(w), md.get("wat").unwrap());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let meta = std::fs::metadata(dir.path().join("data.mdb")).unwrap();
+            assert_eq!(0o600, meta.permissions().mode() & 0o777);
+        }
+
+        // the environment is shared, so it can only be opened again with the same options
+        assert!(builder.open(dir.path()).is_ok());
+        assert_eq!(
+            MegadexDbError::EnvOptionsDiffer(dir.path().display().to_string()),
+            Db::new(dir.path()).err().unwrap()
+        );
+    }
+
+    #[test]
+    fn temp_dir() {
+        let db = Db::new_temp().unwrap();
+        let path = db.path().to_path_buf();
+        assert!(path.join("data.mdb").exists());
+
+        let md: MegadexDb<Weee> = MegadexDb::new(db.clone(), &["b"][..]).unwrap();
+        drop(db);
+        assert!(path.is_dir());
+        assert_eq!(None, md.get("wat").unwrap());
+        drop(md);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn lifecycle() {
+        let root = tempfile::Builder::new().prefix("megadex").tempdir().unwrap();
+        let dir = root.path().join("db");
+        std::fs::create_dir(&dir).unwrap();
+        let w = Weee {
+            id: "wat".into(),
+            a: 42,
+            b: "lalalala".into(),
+        };
+
+        let db = Db::new(dir.as_path()).unwrap();
+        let md: MegadexDb<Weee> = MegadexDb::new(db.clone(), &["b"][..]).unwrap();
+        md.put(&w.id, &w, &[("b", &w.b)]).unwrap();
+        let in_use = MegadexDbError::EnvInUse(dir.display().to_string());
+        assert_eq!(in_use, Db::destroy(&dir).err().unwrap());
+        assert_eq!(in_use, db.close().err().unwrap());
+        drop(md);
+
+        // the environment was closed along with the last handle, so it can be reopened with other options
+        let db = Db::builder().max_dbs(10).open(&dir).unwrap();
+        let md: MegadexDb<Weee> = MegadexDb::new(db.clone(), &["a", "b", "c", "d", "e", "f"][..]).unwrap();
+        assert_eq!(Some(w), md.get("wat").unwrap());
+        drop(md);
+        db.close().unwrap();
+
+        Db::destroy(&dir).unwrap();
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn read_only() {
+        let res = ReadOnlyMegadexDb::<Weee, Bincode, MemDb>::new(MemDb::new(), &["b"][..]).err().unwrap();
+        assert_eq!(MegadexDbError::StoreNotFound("_main_".into()), res);
+
+        let dir = tempfile::Builder::new().prefix("megadex").tempdir().unwrap();
+        let w = Weee {
+            id: "wat".into(),
+            a: 42,
+            b: "lalalala".into(),
+        };
+        {
+            let md: MegadexDb<Weee> = MegadexDb::new(Db::new(dir.path()).unwrap(), &["b"][..]).unwrap();
+            md.put(&w.id, &w, &[("b", &w.b)]).unwrap();
+        }
+
+        let db = Db::open_read_only(dir.path()).unwrap();
+        let res = ReadOnlyMegadexDb::<Weee>::new(db.clone(), &["b", "c"][..]).err().unwrap();
+        assert_eq!(MegadexDbError::StoreNotFound("c".into()), res);
+
+        let md: ReadOnlyMegadexDb<Weee> = ReadOnlyMegadexDb::new(db, &["b"][..]).unwrap();
+        assert_eq!(Some(w.clone()), md.get(&w.id).unwrap());
+        assert_eq!(vec![w.clone()], md.get_by_field(B, &w.b).unwrap());
+        let ids: Vec<String> = md.ids_by_field(B, &w.b).unwrap();
+        assert_eq!(vec![w.id], ids);
+    }
+
+    #[test]
+    fn catalog() {
+        let dir = tempfile::Builder::new().prefix("megadex").tempdir().unwrap();
+        let db = Db::new(dir.path()).unwrap();
+        let md = MegadexDb::<Weee>::new(db.clone(), &["a", "b"][..]).unwrap();
+        let w = Weee {
+            id: "wat".into(),
+            a: 42,
+            b: "lalalala".into(),
+        };
+        md.put(&w.id, &w, &[("b", &w.b)]).unwrap();
+        let store = |name: &str, kind, entries| StoreInfo {
+            name: name.into(),
+            kind,
+            entries,
+        };
+        let expected = vec![
+            store("_main_", StoreKind::Single, 1),
+            store("_megadex_", StoreKind::Single, 1),
+            store("a", StoreKind::Multi, 0),
+            store("b", StoreKind::Multi, 1),
+        ];
+        assert_eq!(expected, db.stores().unwrap());
+        drop(md);
+        db.close().unwrap();
+
+        // a fresh environment finds the stores in the unnamed database without opening them
+        let db = Db::open_read_only(dir.path()).unwrap();
+        assert_eq!(expected, db.stores().unwrap());
+        db.close().unwrap();
+        let db = Db::builder().max_dbs(1).read_only(true).open(dir.path()).unwrap();
+        assert_eq!(expected, db.stores().unwrap());
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    struct Tally {
+        count: u8,
+        name: String,
+    }
+
+    impl Record for Tally {
+        type Id = String;
+
+        fn fields() -> &'static [&'static str] {
+            &[]
+        }
+
+        fn id(&self) -> &String {
+            &self.name
+        }
+
+        fn index_keys<C: Codec>(&self) -> Result<Vec<(&'static str, Vec<u8>)>, MegadexDbError> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[test]
+    fn record_format() {
+        // encodings which start with the high bits of the header byte
+        let long = Weee {
+            id: "x".repeat(240),
+            a: 42,
+            b: "lalalala".into(),
+        };
+        let tally = Tally {
+            count: 241,
+            name: "wat".into(),
+        };
+        let blob = bincode::serialize(&long).unwrap();
+        assert_eq!(240, blob[0]);
+        assert_eq!(241, bincode::serialize(&tally).unwrap()[0]);
+
+        let md: MegadexDb<Weee, Bincode, MemDb> = MegadexDb::new(MemDb::new(), &["b"][..]).unwrap();
+        md.put(&long.id, &long, &[("b", &long.b)]).unwrap();
+        assert_eq!(Some(long.clone()), md.get(&long.id).unwrap());
+        let md: MegadexDb<Tally, Bincode, MemDb> = MegadexDb::new(MemDb::new(), &[][..]).unwrap();
+        md.put(&tally.name, &tally, &[]).unwrap();
+        assert_eq!(Some(tally.clone()), md.get(&tally.name).unwrap());
+
+        // a record written by an older version has no header until the collection is opened for writing
+        let db = Db::new_temp().unwrap();
+        let main = db.open_single(MAIN_STORE, true).unwrap();
+        let id = bincode::serialize(&long.id).unwrap();
+        db.write(|txn| txn.put(main, &id, &blob)).unwrap();
+        assert!(!is_framed(&db).unwrap());
+        let ro = ReadOnlyMegadexDb::<Weee>::new(db.clone(), &[][..]).unwrap();
+        assert_eq!(Some(long.clone()), ro.get(&long.id).unwrap());
+
+        let md = MegadexDb::<Weee>::new(db.clone(), &[][..]).unwrap();
+        assert!(is_framed(&db).unwrap());
+        assert_eq!(Some(long.clone()), md.get(&long.id).unwrap());
+        let framed = db.read(|txn| txn.get(main, &id)).unwrap().unwrap();
+        assert_eq!((0xf0, &blob[..]), (framed[0], &framed[1..]));
+        let ro = ReadOnlyMegadexDb::<Weee>::new(db, &[][..]).unwrap();
+        assert_eq!(Some(long.clone()), ro.get(&long.id).unwrap());
+    }
+
+    #[test]
+    fn subscribe() {
+        let md: MegadexDb<Weee, Bincode, MemDb> = MegadexDb::new(MemDb::new(), &["b"][..]).unwrap();
+        let w = Weee {
+            id: "wat".into(),
+            a: 42,
+            b: "lalalala".into(),
+        };
+        let changes = md.subscribe().unwrap();
+        let gone = md.subscribe().unwrap();
+        drop(gone);
+
+        md.put(&w.id, &w, &[]).unwrap();
+        let w2 = Weee {
+            a: 43,
+            ..w.clone()
+        };
+        md.put(&w.id, &w2, &[]).unwrap();
+        assert!(md.del(&"nope".to_string(), &[]).is_err());
+        md.del(&w.id, &[]).unwrap();
+
+        assert_eq!(Change::Inserted(w.clone()), changes.recv().unwrap());
+        assert_eq!(
+            Change::Updated {
+                old: w,
+                new: w2.clone(),
+            },
+            changes.recv().unwrap()
+        );
+        assert_eq!(Change::Deleted(w2), changes.recv().unwrap());
+        assert!(changes.try_recv().is_err());
+    }
+
+    #[test]
+    fn expiry() {
+        let md: MegadexDb<Weee, Bincode, MemDb> = MegadexDb::new(MemDb::new(), &["b"][..]).unwrap();
+        let w = Weee {
+            id: "wat".into(),
+            a: 42,
+            b: "lalalala".into(),
+        };
+        let ttl = std::time::Duration::from_secs(60);
+        assert!(md.put_with_ttl(&w.id, &w, &[("b", &w.b)], ttl).is_err());
+
+        let md = md.with_expiry().unwrap();
+        md.put_with_ttl(&w.id, &w, &[("b", &w.b)], ttl).unwrap();
+        assert_eq!(Some(w.clone()), md.get(&w.id).unwrap());
+        md.put_with_ttl(&w.id, &w, &[], std::time::Duration::from_secs(0)).unwrap();
+        assert_eq!(None, md.get(&w.id).unwrap());
+        assert!(md.get_by_field(B, &w.b).unwrap().is_empty());
+
+        // a plain put drops the deadline
+        md.put(&w.id, &w, &[]).unwrap();
+        assert_eq!(vec![w.clone()], md.get_by_field(B, &w.b).unwrap());
+    }
+
+    #[test]
+    fn versions() {
+        let md: MegadexDb<Weee, Bincode, MemDb> = MegadexDb::new(MemDb::new(), &["b"][..]).unwrap();
+        let mut w = Weee {
+            id: "wat".into(),
+            a: 42,
+            b: "lalalala".into(),
+        };
+        assert!(md.put_if_version(&w.id, &w, &[], 0).is_err());
+
+        let md = md.with_version_field(|w, v| w.a = v as u32);
+        assert_eq!(1, md.put_if_version(&w.id, &w, &[("b", &w.b)], 0).unwrap());
+        assert_eq!(Err(MegadexDbError::VersionConflict(0, 1)), md.put_if_version(&w.id, &w, &[], 0));
+        assert_eq!(2, md.put_if_version(&w.id, &w, &[], 1).unwrap());
+        md.put(&w.id, &w, &[]).unwrap();
+        assert_eq!(Some(3), md.version(&w.id).unwrap());
+
+        w.a = 3;
+        assert_eq!(Some(w.clone()), md.get(&w.id).unwrap());
+        assert_eq!(vec![w.clone()], md.get_by_field(B, &w.b).unwrap());
+
+        md.del(&w.id, &[("b", &w.b)]).unwrap();
+        assert_eq!(None, md.version(&w.id).unwrap());
+    }
+
+    #[test]
+    fn envelope() {
+        let db = MemDb::new();
+        let w = |a| Weee {
+            id: format!("w{}", a),
+            a,
+            b: "lalalala".into(),
+        };
+        // a plain collection writes the encoded value as is
+        let plain: MegadexDb<Weee, Bincode, MemDb> = MegadexDb::new(db.clone(), &["b"][..]).unwrap();
+        plain.put(&w(1).id, &w(1), &[]).unwrap();
+
+        let md: MegadexDb<Weee, Bincode, MemDb> = MegadexDb::new(db, &["b"][..]).unwrap();
+        let md = md.with_versions().with_compression(Compression::Uncompressed);
+        assert_eq!(Some(w(1)), md.get(&w(1).id).unwrap());
+        assert_eq!(Some(1), md.version(&w(1).id).unwrap());
+        assert_eq!(2, md.put_if_version(&w(1).id, &w(1), &[], 1).unwrap());
+        md.put(&w(2).id, &w(2), &[]).unwrap();
+
+        // the header says how a blob was written, whatever the collection is configured with
+        assert_eq!(Some(w(1)), plain.get(&w(1).id).unwrap());
+        assert_eq!(Some(w(2)), plain.get(&w(2).id).unwrap());
+    }
+
+    #[test]
+    fn history() {
+        let md: MegadexDb<Weee, Bincode, MemDb> = MegadexDb::new(MemDb::new(), &["b"][..]).unwrap();
+        let md = md.with_history(Retention::count(3)).unwrap();
+        let w = |a| Weee {
+            id: "wat".into(),
+            a,
+            b: "lalalala".into(),
+        };
+        let before = SystemTime::now() - std::time::Duration::from_secs(1);
+        md.put(&w(1).id, &w(1), &[]).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let first = SystemTime::now();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        md.put(&w(2).id, &w(2), &[]).unwrap();
+
+        let history = md.history(&w(0).id).unwrap();
+        assert_eq!(vec![0, 1], history.iter().map(|r| r.seq).collect::<Vec<_>>());
+        assert_eq!(vec![None, Some(w(1))], history.into_iter().map(|r| r.value).collect::<Vec<_>>());
+        assert_eq!(None, md.get_as_of(&w(0).id, before).unwrap());
+        assert_eq!(Some(w(1)), md.get_as_of(&w(0).id, first).unwrap());
+        assert_eq!(Some(w(2)), md.get_as_of(&w(0).id, SystemTime::now()).unwrap());
+
+        md.del(&w(0).id, &[]).unwrap();
+        md.put(&w(3).id, &w(3), &[]).unwrap();
+        let history = md.history(&w(0).id).unwrap();
+        assert_eq!(vec![1, 2, 3], history.iter().map(|r| r.seq).collect::<Vec<_>>());
+        assert_eq!(vec![Some(w(1)), Some(w(2)), None], history.into_iter().map(|r| r.value).collect::<Vec<_>>());
+
+        let md = md.with_history(Retention::age(std::time::Duration::from_secs(0))).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        assert_eq!(3, md.prune_history().unwrap());
+        assert!(md.history(&w(0).id).unwrap().is_empty());
+
+        // the sequence numbers carry on after the history of a record was dropped
+        md.put(&w(4).id, &w(4), &[]).unwrap();
+        assert_eq!(vec![4], md.history(&w(0).id).unwrap().iter().map(|r| r.seq).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn audit() {
+        let db = MemDb::new();
+        let md: MegadexDb<Weee, Bincode, MemDb> = MegadexDb::new(db.clone(), &["b"][..]).unwrap();
+        let md = md.with_audit("weee").unwrap();
+        let w = Weee {
+            id: "wat".into(),
+            a: 42,
+            b: "lalalala".into(),
+        };
+        md.put(&w.id, &w, &[("b", &w.b)]).unwrap();
+        md.as_actor("bob").del(&w.id, &[("b", &w.b)]).unwrap();
+        assert!(md.del(&w.id, &[]).is_err());
+
+        let log = md.audit_log(0).unwrap();
+        assert_eq!(vec![AuditOp::Put, AuditOp::Delete], log.iter().map(|e| e.op).collect::<Vec<_>>());
+        assert_eq!(vec!["", "bob"], log.iter().map(|e| &e.actor[..]).collect::<Vec<_>>());
+        assert_eq!("weee", log[1].collection);
+        assert_eq!(bincode::serialize(&w.id).unwrap(), log[1].id);
+        assert_eq!(1, md.audit_log(1).unwrap().len());
+        assert_eq!(2, md.verify_audit().unwrap());
+
+        // rewriting an entry breaks the chain from there on
+        let section = Section::open(&db, internal::AUDIT, false).unwrap();
+        let mut forged = log[0].clone();
+        forged.actor = "mallory".into();
+        db.write(|txn| section.put(txn, &0u64.to_be_bytes(), &forged.encode())).unwrap();
+        assert_eq!(Err(MegadexDbError::AuditBroken(0)), md.verify_audit());
+    }
+
+    #[test]
+    fn stats() {
+        let db = Db::new_temp().unwrap();
+        let md: MegadexDb<Weee> = MegadexDb::new(db.clone(), &["b"][..]).unwrap();
+        for (id, b) in [("one", "x"), ("two", "x"), ("three", "y")].iter() {
+            let w = Weee {
+                id: id.to_string(),
+                a: 42,
+                b: b.to_string(),
+            };
+            md.put(&w.id, &w, &[("b", &w.b)]).unwrap();
+        }
+
+        // too large for a leaf page, and left out of the index
+        let big = Weee {
+            id: "four".into(),
+            a: 42,
+            b: "z".repeat(20_000),
+        };
+        md.put(&big.id, &big, &[]).unwrap();
+
+        let stats = md.stats().unwrap();
+        assert_eq!(4, stats.records);
+        assert_eq!(stats.blob_bytes / 4, stats.avg_blob_bytes);
+        let pages = stats.pages.unwrap();
+        assert_eq!(4, pages.entries);
+        assert!(pages.depth > 0 && pages.overflow_pages > 0);
+        assert_eq!(1, stats.indexes.len());
+        let index = &stats.indexes[0];
+        assert_eq!(("b", 2, 3), (&index.name[..], index.keys, index.entries));
+        let pages = index.pages.unwrap();
+        assert_eq!((3, 0), (pages.entries, pages.overflow_pages));
+
+        let stats = db.stats().unwrap();
+        let main = stats.stores.iter().find(|s| s.name == MAIN_STORE).unwrap();
+        assert_eq!((StoreKind::Single, 4, 4), (main.kind, main.keys, main.entries));
+        assert_eq!(4, main.pages.entries);
+        assert!(main.pages.overflow_pages > 0);
+        let index = stats.stores.iter().find(|s| s.name == "b").unwrap();
+        assert_eq!((StoreKind::Multi, 2, 3), (index.kind, index.keys, index.entries));
+        assert_eq!(3, index.pages.entries);
+        assert!(stats.map_used > 0 && stats.map_used <= stats.map_size);
+
+        let md: MegadexDb<Weee, Bincode, MemDb> = MegadexDb::new(MemDb::new(), &["b"][..]).unwrap();
+        assert_eq!(None, md.stats().unwrap().pages);
+    }
+
+    #[test]
+    fn concurrency() {
+        fn shareable<S: Send + Sync + Clone>(_: &S) {}
+
+        let md: MegadexDb<Weee> = MegadexDb::new(Db::new_temp().unwrap(), &["b"][..]).unwrap();
+        shareable(&md);
+        let writers = (0..4).map(|t| {
+            let md = md.clone();
+            std::thread::spawn(move || {
+                for a in 0..100 {
+                    let w = Weee {
+                        id: format!("w{}-{}", t, a),
+                        a,
+                        b: format!("t{}", t),
+                    };
+                    md.put(&w.id, &w, &[("b", &w.b)]).unwrap();
+                }
+            })
+        });
+        let readers = (0..4).map(|t| {
+            let md = md.clone();
+            std::thread::spawn(move || {
+                // a record is only ever seen complete, along with its index entry
+                for _ in 0..100 {
+                    for w in md.get_by_field(B, &format!("t{}", t)).unwrap() {
+                        assert_eq!(Some(w.clone()), md.get(&w.id).unwrap());
+                    }
+                }
+            })
+        });
+        let threads: Vec<_> = writers.chain(readers).collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        for t in 0..4 {
+            assert_eq!(100, md.get_by_field(B, &format!("t{}", t)).unwrap().len());
+        }
+    }
+
+    #[test]
+    fn backup() {
+        let db = Db::new_temp().unwrap();
+        let other: MegadexDb<Weee> = MegadexDb::new(db.clone(), &["x"][..]).unwrap();
+        let w = Weee {
+            id: "wat".into(),
+            a: 42,
+            b: "lalalala".into(),
+        };
+        other.put(&w.id, &w, &[("x", &w.b)]).unwrap();
+
+        let writer = {
+            let db = db.clone();
+            std::thread::spawn(move || {
+                let md: MegadexDb<Weee> = MegadexDb::new(db, &["b"][..]).unwrap();
+                for a in 0..500 {
+                    let w = Weee {
+                        id: format!("w{}", a),
+                        a,
+                        b: format!("b{}", a % 7),
+                    };
+                    md.put(&w.id, &w, &[("b", &w.b)]).unwrap();
+                }
+            })
+        };
+        let root = tempfile::Builder::new().prefix("megadex").tempdir().unwrap();
+        for (i, compact) in [false, true].iter().enumerate() {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            let dest = root.path().join(format!("backup{}", i));
+            db.backup_to(&dest, *compact).unwrap();
+            assert!(db.backup_to(&dest, *compact).is_err());
+
+            let copy = Db::new(dest.as_path()).unwrap();
+            let other: MegadexDb<Weee> = MegadexDb::new(copy.clone(), &["x"][..]).unwrap();
+            assert_eq!(vec![w.clone()], other.get_by_field(Field::<Weee, String>::new("x"), &w.b).unwrap());
+
+            // every record in the copy is complete with its index entries
+            let md: MegadexDb<Weee> = MegadexDb::new(copy, &["b"][..]).unwrap();
+            let mut count = 0;
+            for b in 0..7 {
+                for v in md.get_by_field(B, &format!("b{}", b)).unwrap() {
+                    assert_eq!(Some(v.clone()), md.get(&v.id).unwrap());
+                    count += 1;
+                }
+            }
+            let mut stored = 0;
+            md.db.read(|txn| txn.scan(md.main, None, &mut |_, _| {
+                stored += 1;
+                Ok(true)
+            })).unwrap();
+            assert_eq!(stored, count + 1);
+        }
+        writer.join().unwrap();
+
+        let restored = root.path().join("restored");
+        Db::restore_from(root.path().join("backup1"), &restored).unwrap();
+        let md: MegadexDb<Weee> = MegadexDb::new(Db::new(restored.as_path()).unwrap(), &["x"][..]).unwrap();
+        assert_eq!(Some(w), md.get("wat").unwrap());
+        assert_eq!(
+            MegadexDbError::EnvInUse(restored.display().to_string()),
+            Db::restore_from(root.path().join("backup0"), &restored).err().unwrap()
+        );
+    }
+
+    fn fill(md: &MegadexDb<Weee>, n: u32) -> Result<(), MegadexDbError> {
+        for a in 0..n {
+            let w = Weee {
+                id: format!("w{}", a),
+                a,
+                b: "x".repeat(1024),
+            };
+            md.put(&w.id, &w, &[])?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn map_growth() {
+        let dir = tempfile::Builder::new().prefix("megadex").tempdir().unwrap();
+        let db = Db::builder().map_size(1 << 16).open(dir.path()).unwrap();
+        let md: MegadexDb<Weee> = MegadexDb::new(db, &[][..]).unwrap();
+        let res = fill(&md, 128).err().unwrap();
+        assert!(matches!(res, MegadexDbError::RkvError(rkv::StoreError::LmdbError(lmdb::Error::MapFull))));
+
+        let dir = tempfile::Builder::new().prefix("megadex").tempdir().unwrap();
+        let builder = Db::builder().map_size(1 << 16).map_growth(2.0, 1 << 20);
+        let db = builder.open(dir.path()).unwrap();
+        let md: MegadexDb<Weee> = MegadexDb::new(db.clone(), &[][..]).unwrap();
+        fill(&md, 128).unwrap();
+        let stats = db.resize_stats();
+        assert!(stats.resizes >= 1);
+        assert_eq!(0, stats.exhausted);
+        assert_eq!(stats.map_size, db.env.read().unwrap().info().unwrap().map_size());
+        // the growth is tracked per environment, not per handle
+        assert_eq!(stats, builder.open(dir.path()).unwrap().resize_stats());
+        assert_eq!(Some(127), md.get("w127").unwrap().map(|w| w.a));
+
+        // the ceiling is respected
+        let res = fill(&md, 2048).err().unwrap();
+        assert!(matches!(res, MegadexDbError::RkvError(rkv::StoreError::LmdbError(lmdb::Error::MapFull))));
+        let stats = db.resize_stats();
+        assert_eq!(1 << 20, stats.map_size);
+        assert_eq!(1, stats.exhausted);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn compression() {
+        let db = Db::new_temp().unwrap();
+        let w = Weee {
+            id: "wat".into(),
+            a: 42,
+            b: "la".repeat(500),
+        };
+        let x = Weee {
+            id: "baz".into(),
+            a: 64,
+            b: "lalalala".into(),
+        };
+
+        let md: MegadexDb<Weee> =
+            MegadexDb::new(db.clone(), &["b"][..]).unwrap().with_compression(Compression::Uncompressed);
+        md.put(&x.id, &x, &[("b", &x.b)]).unwrap();
+
+        let md: MegadexDb<Weee> = MegadexDb::new(db, &["b"][..]).unwrap().with_compression(Compression::zstd());
+        md.put(&w.id, &w, &[]).unwrap();
+        let blob = md.db.read(|txn| txn.get(md.main, &bincode::serialize(&w.id).unwrap())).unwrap().unwrap();
+        assert!(blob.len() < 100);
+
+        assert_eq!(Some(w.clone()), md.get(&w.id).unwrap());
+        assert_eq!(Some(x.clone()), md.get(&x.id).unwrap());
+
+        let dict = md.train_dictionary(1024);
+        assert!(dict.is_err() || dict.unwrap().len() <= 1024);
+    }
+
+    #[test]
+    fn uncompressed() {
+        let db = Db::new_temp().unwrap();
+        let x = Weee {
+            id: "baz".into(),
+            a: 64,
+            b: "lalalala".into(),
+        };
+        let w = Weee {
+            id: "wat".into(),
+            a: 42,
+            b: "la".repeat(500),
+        };
+
+        // records written before compression was configured have no header
+        let md: MegadexDb<Weee> = MegadexDb::new(db.clone(), &["b"][..]).unwrap();
+        md.put(&x.id, &x, &[("b", &x.b)]).unwrap();
+
+        let md: MegadexDb<Weee> = MegadexDb::new(db, &["b"][..]).unwrap().with_compression(Compression::Uncompressed);
+        assert_eq!(Some(x.clone()), md.get(&x.id).unwrap());
+        md.put(&w.id, &w, &[]).unwrap();
+        let blob = md.db.read(|txn| txn.get(md.main, &bincode::serialize(&w.id).unwrap())).unwrap().unwrap();
+        assert_eq!(bincode::serialize(&w).unwrap(), blob[2..].to_vec());
+        assert_eq!(Some(w.clone()), md.get(&w.id).unwrap());
+        assert_eq!(Some(x.clone()), md.get(&x.id).unwrap());
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn lz4() {
+        let db = Db::new_temp().unwrap();
+        let x = Weee {
+            id: "baz".into(),
+            a: 64,
+            b: "lalalala".into(),
+        };
+        let w = Weee {
+            id: "wat".into(),
+            a: 42,
+            b: "la".repeat(500),
+        };
+
+        let md: MegadexDb<Weee> = MegadexDb::new(db.clone(), &["b"][..]).unwrap();
+        md.put(&x.id, &x, &[("b", &x.b)]).unwrap();
+
+        let md: MegadexDb<Weee> = MegadexDb::new(db, &["b"][..]).unwrap().with_compression(Compression::Lz4);
+        md.put(&w.id, &w, &[]).unwrap();
+        let blob = md.db.read(|txn| txn.get(md.main, &bincode::serialize(&w.id).unwrap())).unwrap().unwrap();
+        assert!(blob.len() < 100);
+
+        assert_eq!(Some(w.clone()), md.get(&w.id).unwrap());
+        assert_eq!(Some(x.clone()), md.get(&x.id).unwrap());
+
+        // records which don't get any smaller are stored as they are, behind the header
+        md.put(&x.id, &x, &[("b", &x.b)]).unwrap();
+        assert_eq!(Some(x.clone()), md.get(&x.id).unwrap());
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn encryption() {
+        let db = Db::new_temp().unwrap();
+        let w = Weee {
+            id: "wat".into(),
+            a: 42,
+            b: "lalalala".into(),
+        };
+
+        let keys = StaticKeys::new(1, [7; 32]);
+        let md: MegadexDb<Weee> = MegadexDb::new(db.clone(), &["b"][..])
+            .unwrap()
+            .with_encryption(Arc::new(keys.clone()))
+            .with_history(Retention::keep_all())
+            .unwrap();
+        md.put(&w.id, &w, &[("b", &w.b)]).unwrap();
+        md.put(&w.id, &w, &[("b", &w.b)]).unwrap();
+        assert_eq!(Some(w.clone()), md.get(&w.id).unwrap());
+        assert_eq!(vec![w.clone()], md.get_by_field(B, &w.b).unwrap());
+
+        let md: MegadexDb<Weee> = MegadexDb::new(db.clone(), &["b"][..])
+            .unwrap()
+            .with_encryption(Arc::new(keys.rotate(2, [9; 32])))
+            .with_history(Retention::keep_all())
+            .unwrap();
+        assert_eq!(Some(w.clone()), md.get(&w.id).unwrap());
+        // the record and its one prior state that existed
+        assert_eq!(2, md.rekey().unwrap());
+        assert_eq!(0, md.rekey().unwrap());
+
+        let md: MegadexDb<Weee> = MegadexDb::new(db.clone(), &["b"][..])
+            .unwrap()
+            .with_encryption(Arc::new(StaticKeys::new(2, [9; 32])))
+            .with_history(Retention::keep_all())
+            .unwrap();
+        assert_eq!(Some(w.clone()), md.get(&w.id).unwrap());
+        let history = md.history(&w.id).unwrap();
+        assert_eq!(vec![None, Some(w.clone())], history.into_iter().map(|r| r.value).collect::<Vec<_>>());
+
+        let md: MegadexDb<Weee> =
+            MegadexDb::new(db, &["b"][..]).unwrap().with_encryption(Arc::new(StaticKeys::new(1, [7; 32])));
+        assert_eq!(Err(MegadexDbError::CryptoError("Unknown key id 2".into())), md.get(&w.id));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_codec() {
+        let db = Db::new_temp().unwrap();
+        let md: MegadexDb<Weee, Json> = MegadexDb::new(db, &["b"][..]).unwrap();
+        let w = Weee {
+            id: "wat".into(),
+            a: 42,
+            b: "lalalala".into(),
+        };
+
+        md.put(&w.id, &w, &[("b", &w.b)]).unwrap();
+        assert_eq!(Some(w.clone()), md.get(&w.id).unwrap());
+        assert_eq!(vec![w.clone()], md.get_by_field(B, &w.b).unwrap());
+
+        let ids: Vec<String> = md.ids_by_field(B, &w.b).unwrap();
+        assert_eq!(ids, vec![w.id.clone()]);
+        let raw = md.db.read(|txn| txn.get(md.main, b"\"wat\"")).unwrap().unwrap();
+        assert_eq!(&raw[1..], &br#"{"id":"wat","a":42,"b":"lalalala"}"#[..]);
     }
 }
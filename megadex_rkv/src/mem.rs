@@ -0,0 +1,276 @@
+use crate::backend::{
+    Backend,
+    ReadTxn,
+    Visitor,
+    WriteTxn,
+};
+use crate::error::MegadexDbError;
+use std::collections::{
+    BTreeMap,
+    BTreeSet,
+    HashMap,
+};
+use std::panic::{
+    self,
+    AssertUnwindSafe,
+};
+use std::sync::{
+    Arc,
+    RwLock,
+};
+
+type Single = BTreeMap<Vec<u8>, Vec<u8>>;
+type Multi = BTreeMap<Vec<u8>, BTreeSet<Vec<u8>>>;
+
+/// Handle to a single-value store of a `MemDb`
+#[derive(Clone, Copy, Debug)]
+pub struct MemSingle(usize);
+
+/// Handle to a multi-value store of a `MemDb`
+#[derive(Clone, Copy, Debug)]
+pub struct MemMulti(usize);
+
+#[derive(Default)]
+struct MemEnv {
+    single_names: HashMap<String, usize>,
+    multi_names: HashMap<String, usize>,
+    singles: Vec<Single>,
+    multis: Vec<Multi>,
+}
+
+/// A purely in-memory database environment, with the same semantics as `Db`.
+/// Handy for tests and ephemeral caches.  Nothing is persisted, the data is gone
+/// when the last clone of the `MemDb` is dropped.
+///
+/// Readers see a consistent view of the data, but unlike LMDB a write transaction
+/// blocks readers until it is committed or rolled back.
+#[derive(Clone, Default)]
+pub struct MemDb {
+    env: Arc<RwLock<MemEnv>>,
+}
+
+impl MemDb {
+    pub fn new() -> MemDb {
+        MemDb::default()
+    }
+}
+
+impl Backend for MemDb {
+    type Single = MemSingle;
+    type Multi = MemMulti;
+
+    fn open_single(&self, name: &str, create: bool) -> Result<MemSingle, MegadexDbError> {
+        let mut env = self.env.write()?;
+        if env.multi_names.contains_key(name) {
+            return Err(MegadexDbError::InvalidType("single store".into(), format!("multi store {}", name)));
+        }
+        if let Some(idx) = env.single_names.get(name) {
+            return Ok(MemSingle(*idx));
+        }
+        if !create {
+            return Err(MegadexDbError::StoreNotFound(name.into()));
+        }
+        let idx = env.singles.len();
+        env.singles.push(Single::new());
+        env.single_names.insert(name.into(), idx);
+        Ok(MemSingle(idx))
+    }
+
+    fn open_multi(&self, name: &str, create: bool) -> Result<MemMulti, MegadexDbError> {
+        let mut env = self.env.write()?;
+        if env.single_names.contains_key(name) {
+            return Err(MegadexDbError::InvalidType("multi store".into(), format!("single store {}", name)));
+        }
+        if let Some(idx) = env.multi_names.get(name) {
+            return Ok(MemMulti(*idx));
+        }
+        if !create {
+            return Err(MegadexDbError::StoreNotFound(name.into()));
+        }
+        let idx = env.multis.len();
+        env.multis.push(Multi::new());
+        env.multi_names.insert(name.into(), idx);
+        Ok(MemMulti(idx))
+    }
+
+    fn read<R, F>(&self, f: F) -> Result<R, MegadexDbError>
+    where
+        F: FnOnce(&dyn ReadTxn<Self>) -> Result<R, MegadexDbError>,
+    {
+        let env = self.env.read()?;
+        f(&MemRead(&env))
+    }
+
+    fn write<R, F>(&self, mut f: F) -> Result<R, MegadexDbError>
+    where
+        F: FnMut(&mut dyn WriteTxn<Self>) -> Result<R, MegadexDbError>,
+    {
+        let mut env = self.env.write()?;
+        let mut txn = MemWrite {
+            env: &mut env,
+            undo: Vec::new(),
+        };
+        // like an LMDB transaction, a write that panics leaves nothing behind
+        let res = match panic::catch_unwind(AssertUnwindSafe(|| f(&mut txn))) {
+            Ok(res) => res,
+            Err(panic) => {
+                txn.rollback();
+                drop(env);
+                panic::resume_unwind(panic);
+            },
+        };
+        if res.is_err() {
+            txn.rollback();
+        }
+        res
+    }
+}
+
+/// A change made by a write transaction, recorded so that it can be reverted
+enum Undo {
+    /// The previous value of a key in a single store
+    Single(usize, Vec<u8>, Option<Vec<u8>>),
+    /// A value that was added to a multi store
+    DupAdded(usize, Vec<u8>, Vec<u8>),
+    /// A value that was removed from a multi store
+    DupRemoved(usize, Vec<u8>, Vec<u8>),
+}
+
+struct MemRead<'e>(&'e MemEnv);
+
+struct MemWrite<'e> {
+    env: &'e mut MemEnv,
+    undo: Vec<Undo>,
+}
+
+impl<'e> MemWrite<'e> {
+    fn rollback(&mut self) {
+        while let Some(undo) = self.undo.pop() {
+            match undo {
+                Undo::Single(store, key, Some(value)) => {
+                    self.env.singles[store].insert(key, value);
+                },
+                Undo::Single(store, key, None) => {
+                    self.env.singles[store].remove(&key);
+                },
+                Undo::DupAdded(store, key, value) => {
+                    remove_dup(&mut self.env.multis[store], &key, &value);
+                },
+                Undo::DupRemoved(store, key, value) => {
+                    self.env.multis[store].entry(key).or_default().insert(value);
+                },
+            }
+        }
+    }
+}
+
+fn remove_dup(store: &mut Multi, key: &[u8], value: &[u8]) -> bool {
+    let (removed, empty) = match store.get_mut(key) {
+        Some(values) => (values.remove(value), values.is_empty()),
+        None => (false, false),
+    };
+    if empty {
+        store.remove(key);
+    }
+    removed
+}
+
+fn get(env: &MemEnv, store: MemSingle, key: &[u8]) -> Option<Vec<u8>> {
+    env.singles[store.0].get(key).cloned()
+}
+
+fn get_dup(env: &MemEnv, store: MemMulti, key: &[u8]) -> Vec<Vec<u8>> {
+    env.multis[store.0].get(key).map(|values| values.iter().cloned().collect()).unwrap_or_default()
+}
+
+fn scan(env: &MemEnv, store: MemSingle, from: Option<&[u8]>, f: &mut Visitor) -> Result<(), MegadexDbError> {
+    let from = from.unwrap_or(&[]).to_vec();
+    for (key, value) in env.singles[store.0].range(from..) {
+        if !f(key, value)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn scan_dup(env: &MemEnv, store: MemMulti, f: &mut Visitor) -> Result<(), MegadexDbError> {
+    for (key, values) in env.multis[store.0].iter() {
+        for value in values.iter() {
+            if !f(key, value)? {
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
+impl<'e> ReadTxn<MemDb> for MemRead<'e> {
+    fn get(&self, store: MemSingle, key: &[u8]) -> Result<Option<Vec<u8>>, MegadexDbError> {
+        Ok(get(self.0, store, key))
+    }
+
+    fn get_dup(&self, store: MemMulti, key: &[u8]) -> Result<Vec<Vec<u8>>, MegadexDbError> {
+        Ok(get_dup(self.0, store, key))
+    }
+
+    fn scan(&self, store: MemSingle, from: Option<&[u8]>, f: &mut Visitor) -> Result<(), MegadexDbError> {
+        scan(self.0, store, from, f)
+    }
+
+    fn scan_dup(&self, store: MemMulti, f: &mut Visitor) -> Result<(), MegadexDbError> {
+        scan_dup(self.0, store, f)
+    }
+}
+
+impl<'e> ReadTxn<MemDb> for MemWrite<'e> {
+    fn get(&self, store: MemSingle, key: &[u8]) -> Result<Option<Vec<u8>>, MegadexDbError> {
+        Ok(get(self.env, store, key))
+    }
+
+    fn get_dup(&self, store: MemMulti, key: &[u8]) -> Result<Vec<Vec<u8>>, MegadexDbError> {
+        Ok(get_dup(self.env, store, key))
+    }
+
+    fn scan(&self, store: MemSingle, from: Option<&[u8]>, f: &mut Visitor) -> Result<(), MegadexDbError> {
+        scan(self.env, store, from, f)
+    }
+
+    fn scan_dup(&self, store: MemMulti, f: &mut Visitor) -> Result<(), MegadexDbError> {
+        scan_dup(self.env, store, f)
+    }
+}
+
+impl<'e> WriteTxn<MemDb> for MemWrite<'e> {
+    fn put(&mut self, store: MemSingle, key: &[u8], value: &[u8]) -> Result<(), MegadexDbError> {
+        let prev = self.env.singles[store.0].insert(key.to_vec(), value.to_vec());
+        self.undo.push(Undo::Single(store.0, key.to_vec(), prev));
+        Ok(())
+    }
+
+    fn delete(&mut self, store: MemSingle, key: &[u8]) -> Result<bool, MegadexDbError> {
+        match self.env.singles[store.0].remove(key) {
+            Some(prev) => {
+                self.undo.push(Undo::Single(store.0, key.to_vec(), Some(prev)));
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    fn put_dup(&mut self, store: MemMulti, key: &[u8], value: &[u8]) -> Result<(), MegadexDbError> {
+        let added =
+            self.env.multis[store.0].entry(key.to_vec()).or_default().insert(value.to_vec());
+        if added {
+            self.undo.push(Undo::DupAdded(store.0, key.to_vec(), value.to_vec()));
+        }
+        Ok(())
+    }
+
+    fn delete_dup(&mut self, store: MemMulti, key: &[u8], value: &[u8]) -> Result<bool, MegadexDbError> {
+        let removed = remove_dup(&mut self.env.multis[store.0], key, value);
+        if removed {
+            self.undo.push(Undo::DupRemoved(store.0, key.to_vec(), value.to_vec()));
+        }
+        Ok(removed)
+    }
+}
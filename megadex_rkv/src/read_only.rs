@@ -0,0 +1,202 @@
+use crate::audit::AuditEntry;
+use crate::backend::{
+    Backend,
+    ReadTxn,
+};
+use crate::codec::{
+    Bincode,
+    Codec,
+};
+use crate::compress::Compression;
+#[cfg(feature = "encryption")]
+use crate::crypto::KeyProvider;
+use crate::db::Db;
+use crate::error::MegadexDbError;
+use crate::history::{
+    Retention,
+    Revision,
+};
+use crate::record::{
+    Field,
+    Record,
+};
+use crate::stats::CollectionStats;
+use crate::version::Stamp;
+use crate::MegadexDb;
+use serde::{
+    de::DeserializeOwned,
+    Serialize,
+};
+use std::borrow::Borrow;
+#[cfg(feature = "encryption")]
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// A handle to an existing collection that can only be read, for processes which inspect
+/// or report on a database that is written elsewhere, e.g. one opened with `Db::open_read_only`.
+///
+/// The stores are opened without creating them, and there are no methods that write.
+pub struct ReadOnlyMegadexDb<T, C = Bincode, B: Backend = Db> {
+    md: MegadexDb<T, C, B>,
+}
+
+impl<T, C, B> ReadOnlyMegadexDb<T, C, B>
+where
+    T: Serialize + DeserializeOwned,
+    C: Codec,
+    B: Backend,
+{
+    /// Open the collection and the indexes for `fields`.
+    /// Fails with `StoreNotFound` if any of them doesn't exist.
+    pub fn new(db: B, fields: &[&str]) -> Result<Self, MegadexDbError> {
+        Ok(ReadOnlyMegadexDb {
+            md: MegadexDb::open(db, fields, false)?,
+        })
+    }
+
+    /// The compression the collection was written with, see `MegadexDb::with_compression`
+    pub fn with_compression(self, compression: Compression) -> Self {
+        ReadOnlyMegadexDb {
+            md: self.md.with_compression(compression),
+        }
+    }
+
+    /// The keys the collection was encrypted with, see `MegadexDb::with_encryption`
+    #[cfg(feature = "encryption")]
+    pub fn with_encryption(self, keys: Arc<dyn KeyProvider>) -> Self {
+        ReadOnlyMegadexDb {
+            md: self.md.with_encryption(keys),
+        }
+    }
+
+    /// Hide the records which are past their deadline, see `MegadexDb::with_expiry`
+    pub fn with_expiry(self) -> Result<Self, MegadexDbError> {
+        Ok(ReadOnlyMegadexDb {
+            md: self.md.open_expiry(false)?,
+        })
+    }
+
+    /// Hide the records which have been deleted, see `MegadexDb::with_soft_delete`
+    pub fn with_soft_delete(self) -> Result<Self, MegadexDbError> {
+        Ok(ReadOnlyMegadexDb {
+            md: self.md.open_tombstones(false)?,
+        })
+    }
+
+    /// See `MegadexDb::include_deleted`
+    pub fn include_deleted(&self) -> Self {
+        ReadOnlyMegadexDb {
+            md: self.md.include_deleted(),
+        }
+    }
+
+    /// Read the history of the collection, see `MegadexDb::with_history`
+    pub fn with_history(self) -> Result<Self, MegadexDbError> {
+        Ok(ReadOnlyMegadexDb {
+            md: self.md.open_history(Retention::keep_all(), false)?,
+        })
+    }
+
+    /// See `MegadexDb::history`
+    pub fn history<K>(&self, id: &K) -> Result<Vec<Revision<T>>, MegadexDbError>
+    where
+        T: Record,
+        T::Id: Borrow<K>,
+        K: Serialize + ?Sized,
+    {
+        self.md.history(id)
+    }
+
+    /// See `MegadexDb::get_as_of`
+    pub fn get_as_of<K>(&self, id: &K, time: SystemTime) -> Result<Option<T>, MegadexDbError>
+    where
+        T: Record,
+        T::Id: Borrow<K>,
+        K: Serialize + ?Sized,
+    {
+        self.md.get_as_of(id, time)
+    }
+
+    /// Read the audit log of the database, see `MegadexDb::with_audit`
+    pub fn with_audit(self) -> Result<Self, MegadexDbError> {
+        Ok(ReadOnlyMegadexDb {
+            md: self.md.open_audit("", false)?,
+        })
+    }
+
+    /// See `MegadexDb::audit_log`
+    pub fn audit_log(&self, from: u64) -> Result<Vec<AuditEntry>, MegadexDbError> {
+        self.md.audit_log(from)
+    }
+
+    /// See `MegadexDb::verify_audit`
+    pub fn verify_audit(&self) -> Result<u64, MegadexDbError> {
+        self.md.verify_audit()
+    }
+
+    /// See `MegadexDb::stats`
+    pub fn stats(&self) -> Result<CollectionStats, MegadexDbError> {
+        self.md.stats()
+    }
+
+    /// Read the version counters of the collection, see `MegadexDb::with_versions`
+    pub fn with_versions(self) -> Self {
+        ReadOnlyMegadexDb {
+            md: self.md.with_versions(),
+        }
+    }
+
+    /// See `MegadexDb::with_version_field`
+    pub fn with_version_field(self, stamp: Stamp<T>) -> Self {
+        ReadOnlyMegadexDb {
+            md: self.md.with_version_field(stamp),
+        }
+    }
+
+    /// See `MegadexDb::version`
+    pub fn version<K>(&self, id: &K) -> Result<Option<u64>, MegadexDbError>
+    where
+        T: Record,
+        T::Id: Borrow<K>,
+        K: Serialize + ?Sized,
+    {
+        self.md.version(id)
+    }
+
+    /// See `MegadexDb::get`
+    pub fn get<K>(&self, id: &K) -> Result<Option<T>, MegadexDbError>
+    where
+        T: Record,
+        T::Id: Borrow<K>,
+        K: Serialize + ?Sized,
+    {
+        self.md.get(id)
+    }
+
+    /// See `MegadexDb::get_by_field`
+    pub fn get_by_field<F, K>(&self, field: Field<T, F>, key: &K) -> Result<Vec<T>, MegadexDbError>
+    where
+        F: Borrow<K>,
+        K: Serialize + ?Sized,
+    {
+        self.md.get_by_field(field, key)
+    }
+
+    /// See `MegadexDb::ids_by_field`
+    pub fn ids_by_field<F, K, I>(&self, field: Field<T, F>, key: &K) -> Result<Vec<I>, MegadexDbError>
+    where
+        F: Borrow<K>,
+        K: Serialize + ?Sized,
+        I: DeserializeOwned,
+    {
+        self.md.ids_by_field(field, key)
+    }
+
+    /// See `MegadexDb::ids_by_field_txn`
+    pub fn ids_by_field_txn<X>(&self, txn: &X, name: &str, key: &[u8]) -> Result<Vec<Vec<u8>>, MegadexDbError>
+    where
+        X: ReadTxn<B> + ?Sized,
+    {
+        self.md.ids_by_field_txn(txn, name, key)
+    }
+}
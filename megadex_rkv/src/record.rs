@@ -0,0 +1,70 @@
+use crate::codec::Codec;
+use crate::error::MegadexDbError;
+use serde::{
+    de::DeserializeOwned,
+    Serialize,
+};
+use std::marker::PhantomData;
+use std::time::SystemTime;
+
+/// A struct which knows its own id and index keys, so that `MegadexDb` can maintain
+/// the indexes of a record without being handed the fields, e.g. when importing.
+///
+/// `#[derive(Megadex)]` implements this from the `#[id]` and `#[indexed]` attributes.
+pub trait Record {
+    /// The type of the id field
+    type Id: Serialize + DeserializeOwned;
+
+    /// The names of the indexed fields
+    fn fields() -> &'static [&'static str];
+
+    /// The id of this record
+    fn id(&self) -> &Self::Id;
+
+    /// The name and encoded key of each indexed field
+    fn index_keys<C: Codec>(&self) -> Result<Vec<(&'static str, Vec<u8>)>, MegadexDbError>;
+
+    /// When this record expires, see `MegadexDb::with_expiry`.
+    /// `#[megadex(ttl_field = "...")]` implements this from the named field.
+    fn expires_at(&self) -> Option<SystemTime> {
+        None
+    }
+}
+
+/// An indexed field of `T` whose values are of type `F`, for the lookups by field of `MegadexDb`.
+/// They take any key that `F` borrows, so a key of the wrong type doesn't compile.
+///
+/// `#[derive(Megadex)]` makes these for the `find_by_<member>` and `id_by_<member>` functions.
+///
+/// ```compile_fail
+/// # use megadex_rkv::{Field, MegadexDb, MemDb};
+/// let md: MegadexDb<String, megadex_rkv::Bincode, MemDb> = MegadexDb::new(MemDb::new(), &["name"]).unwrap();
+/// md.get_by_field(Field::<String, String>::new("name"), &42u32);
+/// ```
+pub struct Field<T, F> {
+    name: &'static str,
+    p: PhantomData<fn(&T) -> &F>,
+}
+
+impl<T, F> Field<T, F> {
+    /// The field called `name`, which is up to the caller to match with `F`
+    pub const fn new(name: &'static str) -> Self {
+        Field {
+            name,
+            p: PhantomData,
+        }
+    }
+
+    /// The name of the index of this field
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl<T, F> Clone for Field<T, F> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, F> Copy for Field<T, F> {}
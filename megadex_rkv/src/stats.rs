@@ -0,0 +1,231 @@
+use crate::backend::{
+    Backend,
+    ReadTxn,
+};
+use crate::codec::Codec;
+use crate::db::{
+    Db,
+    StoreKind,
+};
+use crate::error::MegadexDbError;
+use crate::{
+    MegadexDb,
+    MAIN_STORE,
+};
+use serde::{
+    de::DeserializeOwned,
+    Serialize,
+};
+
+/// The B-tree statistics LMDB reports for a store or an environment, see `Backend::page_stats`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PageStats {
+    pub page_size: u32,
+    /// The depth of the B-tree
+    pub depth: u32,
+    pub branch_pages: usize,
+    pub leaf_pages: usize,
+    /// Pages holding values too large to fit in a leaf page
+    pub overflow_pages: usize,
+    pub entries: usize,
+}
+
+impl From<lmdb::Stat> for PageStats {
+    fn from(stat: lmdb::Stat) -> PageStats {
+        PageStats {
+            page_size: stat.page_size(),
+            depth: stat.depth(),
+            branch_pages: stat.branch_pages(),
+            leaf_pages: stat.leaf_pages(),
+            overflow_pages: stat.overflow_pages(),
+            entries: stat.entries(),
+        }
+    }
+}
+
+impl From<lmdb_sys::MDB_stat> for PageStats {
+    fn from(stat: lmdb_sys::MDB_stat) -> PageStats {
+        PageStats {
+            page_size: stat.ms_psize,
+            depth: stat.ms_depth,
+            branch_pages: stat.ms_branch_pages,
+            leaf_pages: stat.ms_leaf_pages,
+            overflow_pages: stat.ms_overflow_pages,
+            entries: stat.ms_entries,
+        }
+    }
+}
+
+/// The size of one index of a collection, see `MegadexDb::stats`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IndexStats {
+    pub name: String,
+    /// The number of distinct field values
+    pub keys: usize,
+    /// The number of (field value, id) pairs
+    pub entries: usize,
+    /// The page statistics of the index store, if the backend keeps any
+    pub pages: Option<PageStats>,
+}
+
+/// The size of a collection, see `MegadexDb::stats`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CollectionStats {
+    /// The number of records in the main store, including expired and deleted ones
+    pub records: usize,
+    /// The indexes, sorted by name
+    pub indexes: Vec<IndexStats>,
+    /// The total size of the stored blobs, after compression and encryption
+    pub blob_bytes: u64,
+    pub avg_blob_bytes: u64,
+    /// The page statistics of the main store, if the backend keeps any
+    pub pages: Option<PageStats>,
+}
+
+/// The size of one named store of a `Db`, see `Db::stats`
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoreStats {
+    pub name: String,
+    pub kind: StoreKind,
+    /// The number of distinct keys
+    pub keys: usize,
+    /// The number of values, which is `keys` for a single-value store
+    pub entries: usize,
+    /// The total size of the keys and values
+    pub bytes: u64,
+    pub pages: PageStats,
+}
+
+/// The size of a `Db` and its stores, see `Db::stats`
+#[derive(Clone, Debug, PartialEq)]
+pub struct DbStats {
+    /// The named stores, sorted by name
+    pub stores: Vec<StoreStats>,
+    /// The size of the memory map
+    pub map_size: usize,
+    /// The part of the memory map which is in use, up to the last page that was written
+    pub map_used: usize,
+    pub last_txnid: usize,
+    pub readers: u32,
+    pub max_readers: u32,
+    /// The page statistics of the unnamed database, which holds an entry for every named store
+    pub pages: PageStats,
+}
+
+impl<T, C, B> MegadexDb<T, C, B>
+where
+    T: Serialize + DeserializeOwned,
+    C: Codec,
+    B: Backend,
+{
+    /// Count the records, index entries and stored bytes of this collection.
+    /// This scans the main store and every index in a single read transaction.
+    pub fn stats(&self) -> Result<CollectionStats, MegadexDbError> {
+        let mut stats = self.db.read(|txn| {
+            let mut stats = CollectionStats::default();
+            txn.scan(self.main, None, &mut |_, blob| {
+                stats.records += 1;
+                stats.blob_bytes += blob.len() as u64;
+                Ok(true)
+            })?;
+            for (name, index) in self.indices.iter() {
+                let (keys, entries) = count_dup(txn, *index)?;
+                stats.indexes.push(IndexStats {
+                    name: name.clone(),
+                    keys,
+                    entries,
+                    pages: None,
+                });
+            }
+            Ok(stats)
+        })?;
+        stats.indexes.sort_by(|a, b| a.name.cmp(&b.name));
+        if stats.records > 0 {
+            stats.avg_blob_bytes = stats.blob_bytes / stats.records as u64;
+        }
+        stats.pages = self.db.page_stats(MAIN_STORE)?;
+        for index in stats.indexes.iter_mut() {
+            index.pages = self.db.page_stats(&index.name)?;
+        }
+        Ok(stats)
+    }
+}
+
+/// The distinct keys and the entries of a multi-value store
+fn count_dup<B, X>(txn: &X, store: B::Multi) -> Result<(usize, usize), MegadexDbError>
+where
+    B: Backend,
+    X: ReadTxn<B> + ?Sized,
+{
+    let (mut keys, mut entries) = (0, 0);
+    let mut last: Option<Vec<u8>> = None;
+    txn.scan_dup(store, &mut |key, _| {
+        if last.as_deref() != Some(key) {
+            keys += 1;
+            last = Some(key.to_vec());
+        }
+        entries += 1;
+        Ok(true)
+    })?;
+    Ok((keys, entries))
+}
+
+impl Db {
+    /// Count the keys, values and bytes of every named store, the internal ones included,
+    /// along with the state of the environment.
+    /// This scans every store, so it takes time in proportion to the size of the database.
+    pub fn stats(&self) -> Result<DbStats, MegadexDbError> {
+        let mut stores = Vec::new();
+        for store in self.stores()? {
+            let (name, kind) = (store.name, store.kind);
+            let (mut keys, mut entries, mut bytes) = (0, 0, 0);
+            match kind {
+                StoreKind::Single => {
+                    let store = self.open_single(&name, false)?;
+                    self.read(|txn| {
+                        txn.scan(store, None, &mut |key, value| {
+                            keys += 1;
+                            entries += 1;
+                            bytes += (key.len() + value.len()) as u64;
+                            Ok(true)
+                        })
+                    })?;
+                },
+                StoreKind::Multi => {
+                    let store = self.open_multi(&name, false)?;
+                    self.read(|txn| {
+                        let mut last: Option<Vec<u8>> = None;
+                        txn.scan_dup(store, &mut |key, value| {
+                            if last.as_deref() != Some(key) {
+                                keys += 1;
+                                last = Some(key.to_vec());
+                            }
+                            entries += 1;
+                            bytes += (key.len() + value.len()) as u64;
+                            Ok(true)
+                        })
+                    })?;
+                },
+            }
+            stores.push(StoreStats {
+                pages: self.store_stat(&name)?,
+                name,
+                kind,
+                keys,
+                entries,
+                bytes,
+            });
+        }
+        stores.sort_by(|a, b| a.name.cmp(&b.name));
+        let (info, stat) = self.env_stats()?;
+        Ok(DbStats {
+            stores,
+            map_size: info.map_size(),
+            map_used: (info.last_pgno() + 1) * stat.page_size() as usize,
+            last_txnid: info.last_txnid(),
+            readers: info.num_readers(),
+            max_readers: info.max_readers(),
+            pages: stat.into(),
+        })
+    }
+}
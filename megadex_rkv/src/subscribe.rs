@@ -0,0 +1,141 @@
+use crate::backend::Backend;
+use crate::codec::Codec;
+use crate::error::MegadexDbError;
+use crate::record::{
+    Field,
+    Record,
+};
+use crate::MegadexDb;
+use serde::{
+    de::DeserializeOwned,
+    Serialize,
+};
+use std::borrow::Borrow;
+use std::sync::mpsc::{
+    channel,
+    Receiver,
+};
+use std::sync::Mutex;
+
+/// A committed change to a record, see `MegadexDb::subscribe`
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change<T> {
+    Inserted(T),
+    Updated {
+        old: T,
+        new: T,
+    },
+    Deleted(T),
+}
+
+impl<T> Change<&T> {
+    fn cloned(&self) -> Change<T>
+    where
+        T: Clone,
+    {
+        match *self {
+            Change::Inserted(new) => Change::Inserted(new.clone()),
+            Change::Updated {
+                old,
+                new,
+            } => Change::Updated {
+                old: old.clone(),
+                new: new.clone(),
+            },
+            Change::Deleted(old) => Change::Deleted(old.clone()),
+        }
+    }
+
+    /// Whether the record matches `filter` before or after the change
+    fn touches(&self, filter: &dyn Fn(&T) -> bool) -> bool {
+        match *self {
+            Change::Inserted(new) => filter(new),
+            Change::Updated {
+                old,
+                new,
+            } => filter(old) || filter(new),
+            Change::Deleted(old) => filter(old),
+        }
+    }
+}
+
+/// Hands a change to a subscriber, returning false once its receiver is gone
+type Deliver<T> = Box<dyn FnMut(&Change<&T>) -> bool + Send>;
+
+/// The subscribers of a collection
+pub(crate) struct Subscribers<T> {
+    list: Mutex<Vec<Deliver<T>>>,
+}
+
+impl<T> Default for Subscribers<T> {
+    fn default() -> Self {
+        Subscribers {
+            list: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl<T> Subscribers<T> {
+    /// Whether a write has to look up the records it replaces or deletes
+    pub(crate) fn active(&self) -> bool {
+        self.list.lock().map(|list| !list.is_empty()).unwrap_or(false)
+    }
+
+    /// Deliver committed changes, dropping the subscribers which have gone away
+    pub(crate) fn notify(&self, changes: &[Change<&T>]) -> Result<(), MegadexDbError> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let mut list = self.list.lock()?;
+        list.retain_mut(|deliver| changes.iter().all(&mut **deliver));
+        Ok(())
+    }
+
+    fn add(&self, deliver: Deliver<T>) -> Result<(), MegadexDbError> {
+        self.list.lock()?.push(deliver);
+        Ok(())
+    }
+}
+
+impl<T, C, B> MegadexDb<T, C, B>
+where
+    T: Serialize + DeserializeOwned + Clone + Send + 'static,
+    C: Codec,
+    B: Backend,
+{
+    /// Receive every change made through this collection, once its transaction has committed.
+    /// Writes look up the records they replace or delete while anyone is subscribed.
+    /// The subscription ends when the receiver is dropped.
+    pub fn subscribe(&self) -> Result<Receiver<Change<T>>, MegadexDbError> {
+        let (sender, receiver) = channel();
+        self.subscribers.add(Box::new(move |change: &Change<&T>| sender.send(change.cloned()).is_ok()))?;
+        Ok(receiver)
+    }
+}
+
+impl<T, C, B> MegadexDb<T, C, B>
+where
+    T: Record + Serialize + DeserializeOwned + Clone + Send + 'static,
+    C: Codec + 'static,
+    B: Backend,
+{
+    /// Like `subscribe`, but only receive the changes to records which are filed under `key`
+    /// in the index `field`, before or after the change.
+    pub fn subscribe_to<F, K>(&self, field: Field<T, F>, key: &K) -> Result<Receiver<Change<T>>, MegadexDbError>
+    where
+        F: Borrow<K>,
+        K: Serialize + ?Sized,
+    {
+        self.index(field.name())?;
+        let wanted = (field.name(), C::encode(key)?);
+        let filter = move |obj: &T| match obj.index_keys::<C>() {
+            Ok(keys) => keys.iter().any(|(f, k)| *f == wanted.0 && *k == wanted.1),
+            Err(_) => false,
+        };
+        let (sender, receiver) = channel();
+        self.subscribers.add(Box::new(move |change: &Change<&T>| {
+            !change.touches(&filter) || sender.send(change.cloned()).is_ok()
+        }))?;
+        Ok(receiver)
+    }
+}
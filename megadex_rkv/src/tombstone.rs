@@ -0,0 +1,151 @@
+use crate::audit::AuditOp;
+use crate::backend::{
+    Backend,
+    ReadTxn,
+    WriteTxn,
+};
+use crate::codec::Codec;
+use crate::error::MegadexDbError;
+use crate::internal::{
+    self,
+    Section,
+};
+use crate::expiry::{
+    self,
+    id_key,
+    read_time,
+    set_time_txn,
+};
+use crate::record::Record;
+use crate::subscribe::Change;
+use crate::MegadexDb;
+use serde::{
+    de::DeserializeOwned,
+    Serialize,
+};
+use std::borrow::Borrow;
+use std::time::Duration;
+
+fn not_enabled() -> MegadexDbError {
+    MegadexDbError::ValueError("Soft delete is not enabled for this collection".into())
+}
+
+impl<T, C, B> MegadexDb<T, C, B>
+where
+    T: Serialize + DeserializeOwned,
+    C: Codec,
+    B: Backend,
+{
+    /// Turn deletes into tombstones: a deleted record keeps its value and index entries, but is
+    /// hidden from reads until it is undeleted or purged.
+    pub fn with_soft_delete(self) -> Result<Self, MegadexDbError> {
+        self.open_tombstones(true)
+    }
+
+    pub(crate) fn open_tombstones(mut self, create: bool) -> Result<Self, MegadexDbError> {
+        self.tombstones = Some(Section::open(&self.db, internal::TOMBSTONES, create)?);
+        Ok(self)
+    }
+
+    /// A handle on the same collection whose reads include the deleted records
+    pub fn include_deleted(&self) -> Self {
+        let mut md = self.clone();
+        md.show_deleted = true;
+        md
+    }
+
+    /// When the record with the encoded `id` was deleted, if it has a tombstone
+    pub(crate) fn tombstone<X>(&self, txn: &X, id: &[u8]) -> Result<Option<u64>, MegadexDbError>
+    where
+        X: ReadTxn<B> + ?Sized,
+    {
+        let section = match self.tombstones {
+            Some(section) => section,
+            None => return Ok(None),
+        };
+        match section.get(txn, &id_key(id))? {
+            Some(time) => read_time(&time).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Whether the record with the encoded `id` is hidden by a tombstone
+    pub(crate) fn deleted<X>(&self, txn: &X, id: &[u8]) -> Result<bool, MegadexDbError>
+    where
+        X: ReadTxn<B> + ?Sized,
+    {
+        if self.show_deleted {
+            return Ok(false);
+        }
+        Ok(self.tombstone(txn, id)?.is_some())
+    }
+
+    /// Mark the record with the encoded `id` as deleted within an existing transaction.
+    /// Returns false if there is no such record, or it is deleted already.
+    pub(crate) fn tombstone_txn(&self, txn: &mut dyn WriteTxn<B>, id: &[u8]) -> Result<bool, MegadexDbError> {
+        let section = self.tombstones.ok_or_else(not_enabled)?;
+        if txn.get(self.main, id)?.is_none() || self.tombstone(txn, id)?.is_some() {
+            return Ok(false);
+        }
+        self.keep_history_txn(txn, id)?;
+        set_time_txn(txn, section, id, Some(expiry::now()))?;
+        self.audit_txn(txn, AuditOp::Delete, id)?;
+        Ok(true)
+    }
+
+    /// Remove the tombstone of the record with the encoded `id` within an existing transaction
+    pub(crate) fn clear_tombstone_txn(&self, txn: &mut dyn WriteTxn<B>, id: &[u8]) -> Result<(), MegadexDbError> {
+        match self.tombstones {
+            Some(section) => set_time_txn(txn, section, id, None),
+            None => Ok(()),
+        }
+    }
+
+    /// Bring back a deleted record. Returns the record, or `None` if it wasn't deleted.
+    pub fn undelete<K>(&self, id: &K) -> Result<Option<T>, MegadexDbError>
+    where
+        T: Record,
+        T::Id: Borrow<K>,
+        K: Serialize + ?Sized,
+    {
+        self.tombstones.ok_or_else(not_enabled)?;
+        let keybytes = C::encode(id)?;
+        let restored = self.db.write(|txn| {
+            if self.tombstone(txn, &keybytes)?.is_none() {
+                return Ok(None);
+            }
+            self.keep_history_txn(txn, &keybytes)?;
+            self.clear_tombstone_txn(txn, &keybytes)?;
+            self.audit_txn(txn, AuditOp::Put, &keybytes)?;
+            self.get_txn(txn, &keybytes)
+        })?;
+        if let Some(ref obj) = restored {
+            self.subscribers.notify(&[Change::Inserted(obj)])?;
+        }
+        Ok(restored)
+    }
+}
+
+impl<T, C, B> MegadexDb<T, C, B>
+where
+    T: Record + Serialize + DeserializeOwned,
+    C: Codec,
+    B: Backend,
+{
+    /// Delete the records which were deleted at least `older_than` ago for good, along with their
+    /// index entries. Returns the number of records that were purged.
+    pub fn purge_deleted(&self, older_than: Duration) -> Result<usize, MegadexDbError> {
+        let section = self.tombstones.ok_or_else(not_enabled)?;
+        let until = expiry::now().saturating_sub(older_than.as_millis() as u64);
+        let purged = self.db.write(|txn| {
+            let mut purged = Vec::new();
+            for id in expiry::due(&*txn, section, until)?.iter() {
+                purged.extend(self.drop_record_txn(txn, id, AuditOp::Purge)?);
+            }
+            Ok(purged)
+        })?;
+        let changes: Vec<_> = purged.iter().map(Change::Deleted).collect();
+        self.subscribers.notify(&changes)?;
+        Ok(purged.len())
+    }
+}
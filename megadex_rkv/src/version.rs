@@ -0,0 +1,133 @@
+use crate::backend::{
+    Backend,
+    ReadTxn,
+    WriteTxn,
+};
+use crate::codec::Codec;
+use crate::envelope::{
+    self,
+    Envelope,
+};
+use crate::error::MegadexDbError;
+use crate::record::Record;
+use crate::MegadexDb;
+use serde::{
+    de::DeserializeOwned,
+    Serialize,
+};
+use std::borrow::Borrow;
+
+/// Copies the stored version of a record into one of its fields, see `MegadexDb::with_version_field`
+pub type Stamp<T> = fn(&mut T, u64);
+
+fn not_enabled() -> MegadexDbError {
+    MegadexDbError::ValueError("Versions are not enabled for this collection".into())
+}
+
+impl<T, C, B> MegadexDb<T, C, B>
+where
+    T: Serialize + DeserializeOwned,
+    C: Codec,
+    B: Backend,
+{
+    /// Keep a version counter in front of every blob in the main store, which starts at 1 and is
+    /// bumped by every write. See `put_if_version`.
+    pub fn with_versions(self) -> Self {
+        self.with_version_field(|_, _| {})
+    }
+
+    /// `with_versions`, copying the version of each record that is read into `T` with `stamp`
+    pub fn with_version_field(mut self, stamp: Stamp<T>) -> Self {
+        self.versions = Some(stamp);
+        self
+    }
+
+    /// The version of the record at the given id, or `None` if there is no such record
+    pub fn version<K>(&self, id: &K) -> Result<Option<u64>, MegadexDbError>
+    where
+        T: Record,
+        T::Id: Borrow<K>,
+        K: Serialize + ?Sized,
+    {
+        self.versions.ok_or_else(not_enabled)?;
+        let keybytes = C::encode(id)?;
+        self.db.read(|txn| match txn.get(self.main, &keybytes)? {
+            Some(blob) => Ok(Some(self.version_of(&self.envelope(&blob)?))),
+            None => Ok(None),
+        })
+    }
+
+    /// Store an object of type T indexed by id, as long as the stored record is still at version
+    /// `expected`, which is 0 for a record that doesn't exist yet.
+    /// Fails with `VersionConflict` otherwise. Returns the new version.
+    pub fn put_if_version<K: Serialize>(
+        &self,
+        id: &K,
+        obj: &T,
+        fields: &[(&str, &K)],
+        expected: u64,
+    ) -> Result<u64, MegadexDbError> {
+        self.write_value(id, obj, fields, None, Some(expected))?;
+        Ok(expected + 1)
+    }
+
+    /// The version of a blob of the main store, which is 0 if the collection isn't versioned.
+    /// A record which was written before versions were enabled is at version 1.
+    pub(crate) fn version_of(&self, envelope: &Envelope) -> u64 {
+        match (self.versions, envelope.version) {
+            (None, _) => 0,
+            (Some(_), Some(version)) => version,
+            (Some(_), None) => 1,
+        }
+    }
+
+    /// Check the version of the record with the encoded `id` against `expected`, if given,
+    /// and return the version of the next write
+    pub(crate) fn next_version_txn<X>(&self, txn: &X, id: &[u8], expected: Option<u64>) -> Result<u64, MegadexDbError>
+    where
+        X: ReadTxn<B> + ?Sized,
+    {
+        if self.versions.is_none() {
+            return match expected {
+                Some(_) => Err(not_enabled()),
+                None => Ok(0),
+            };
+        }
+        let current = match txn.get(self.main, id)? {
+            Some(blob) => self.version_of(&self.envelope(&blob)?),
+            None => 0,
+        };
+        match expected {
+            Some(expected) if expected != current => Err(MegadexDbError::VersionConflict(expected, current)),
+            _ => Ok(current + 1),
+        }
+    }
+
+    /// Write a value sealed with `flags` to the main store, behind the envelope header and
+    /// its version if the collection is versioned
+    pub(crate) fn put_blob_txn(
+        &self,
+        txn: &mut dyn WriteTxn<B>,
+        id: &[u8],
+        flags: u8,
+        body: &[u8],
+        version: u64,
+    ) -> Result<(), MegadexDbError> {
+        let version = self.versions.map(|_| version);
+        txn.put(self.main, id, &envelope::frame(flags, version, body))
+    }
+}
+
+impl<T, C, B> MegadexDb<T, C, B>
+where
+    T: Record + Serialize + DeserializeOwned,
+    C: Codec,
+    B: Backend,
+{
+    /// Store `obj` under its own id, moving its index entries over from the record it replaces,
+    /// as long as the stored record is still at version `expected`. Returns the new version.
+    pub fn put_record_if_version(&self, obj: &T, expected: u64) -> Result<u64, MegadexDbError> {
+        self.put_record_checked(obj, Some(expected))?;
+        Ok(expected + 1)
+    }
+}